@@ -0,0 +1,20 @@
+use serde_json::json;
+
+use jwks_client::encoder::{encode, SigningKey};
+use jwks_client::jwt::Header;
+
+fn main() {
+    let key = SigningKey::from_pem("RS256", include_str!("../test/private.pem")).unwrap().with_kid("1");
+
+    let header = Header::new(json!({ "typ": "JWT" }));
+
+    let claims = json!({
+        "iss": "https://chronogears.com/test",
+        "name": "Ada Lovelace",
+        "exp": 500,
+    });
+
+    let token = encode(&header, &claims, &key).unwrap();
+
+    println!("{}", token);
+}
@@ -16,30 +16,33 @@ async fn main() {
         Err(Error {
             msg,
             typ: Type::Header,
+            ..
         }) => {
             eprintln!("Problem with header. Message: {}", msg);
         }
         Err(Error {
             msg,
             typ: Type::Payload,
+            ..
         }) => {
             eprintln!("Problem with payload. Message: {}", msg);
         }
         Err(Error {
             msg,
             typ: Type::Signature,
+            ..
         }) => {
             eprintln!("Problem with signature. Message: {}", msg);
         }
         Err(Error {
-            msg: _,
             typ: Type::Expired,
+            ..
         }) => {
             eprintln!("Token is expired.");
         }
         Err(Error {
-            msg: _,
             typ: Type::Early,
+            ..
         }) => {
             eprintln!("Too early to use token.");
         }
@@ -0,0 +1,498 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::error::{Error, Type};
+use crate::issuers::IssuerKeyStores;
+use crate::jwt::Jwt;
+use crate::keyset::KeyStore;
+use crate::shared::SharedKeyStore;
+
+#[cfg(feature = "actix")]
+pub mod actix;
+#[cfg(feature = "rocket")]
+pub mod rocket;
+#[cfg(feature = "tower")]
+pub mod tower;
+
+/// Verifies bearer tokens for the web framework integrations
+pub trait TokenVerifier: Send + Sync {
+    fn verify_token(&self, token: &str) -> Result<Jwt, Error>;
+}
+
+impl TokenVerifier for KeyStore {
+    fn verify_token(&self, token: &str) -> Result<Jwt, Error> {
+        self.verify(token)
+    }
+}
+
+impl TokenVerifier for SharedKeyStore {
+    fn verify_token(&self, token: &str) -> Result<Jwt, Error> {
+        self.verify(token)
+    }
+}
+
+impl TokenVerifier for IssuerKeyStores {
+    fn verify_token(&self, token: &str) -> Result<Jwt, Error> {
+        self.verify(token)
+    }
+}
+
+impl<T: TokenVerifier + ?Sized> TokenVerifier for Arc<T> {
+    fn verify_token(&self, token: &str) -> Result<Jwt, Error> {
+        (**self).verify_token(token)
+    }
+}
+
+/// Payload of a verified token deserialized into `T` (see `Payload::into`), for framework extractors
+#[derive(Debug, Clone, PartialEq)]
+pub struct Claims<T>(pub T);
+
+impl<T> Claims<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Claims<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Where a request may carry its token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenLocation {
+    /// `Authorization: Bearer <token>` header
+    Header,
+    /// Cookie with the given name
+    Cookie(String),
+    /// Query parameter with the given name (ie: `access_token`)
+    Query(String),
+}
+
+/// How bearer tokens are found in requests and how failures are reported
+#[derive(Debug, Clone)]
+pub struct BearerConfig {
+    locations: Vec<TokenLocation>,
+    realm: Option<String>,
+}
+
+impl Default for BearerConfig {
+    fn default() -> Self {
+        BearerConfig::new()
+    }
+}
+
+impl BearerConfig {
+    /// Tokens are read from the `Authorization` header only
+    pub fn new() -> BearerConfig {
+        BearerConfig {
+            locations: vec![TokenLocation::Header],
+            realm: None,
+        }
+    }
+
+    /// Read tokens from `locations`. A request that has a token in more than one of them is rejected
+    /// as required by RFC 6750.
+    pub fn set_locations(&mut self, locations: Vec<TokenLocation>) {
+        self.locations = locations;
+    }
+
+    pub fn locations(&self) -> &[TokenLocation] {
+        &self.locations
+    }
+
+    /// `realm` of the `WWW-Authenticate` challenge
+    pub fn set_realm(&mut self, realm: &str) {
+        self.realm = Some(realm.to_string());
+    }
+
+    pub fn realm(&self) -> Option<&str> {
+        self.realm.as_deref()
+    }
+
+    /// Find the token of a request given its `Authorization` headers, `Cookie` headers and query string
+    pub fn extract_token<'a, A, C>(&self, authorization: A, cookies: C, query: Option<&'a str>) -> Result<Cow<'a, str>, BearerError>
+    where
+        A: IntoIterator<Item = &'a str>,
+        C: IntoIterator<Item = &'a str>,
+    {
+        let authorization: Vec<&str> = authorization.into_iter().collect();
+        let cookies: Vec<&str> = cookies.into_iter().collect();
+
+        let mut tokens = vec![];
+
+        for location in self.locations.iter() {
+            match location {
+                TokenLocation::Header => tokens.extend(header_tokens(&authorization)?.into_iter().map(Cow::Borrowed)),
+                TokenLocation::Cookie(name) => tokens.extend(cookie_tokens(&cookies, name).map(Cow::Borrowed)),
+                TokenLocation::Query(name) => tokens.extend(query_tokens(query, name)),
+            }
+        }
+
+        if tokens.len() > 1 {
+            return Err(BearerError::invalid_request("More than one token in request"));
+        }
+
+        match tokens.pop() {
+            Some(token) if token.is_empty() => Err(BearerError::invalid_request("Empty token")),
+            Some(token) => Ok(token),
+            None => Err(BearerError::missing()),
+        }
+    }
+
+    /// Find the token of a request (see `extract_token`) and verify it
+    pub fn authenticate<'a, V, A, C>(&self, verifier: &V, authorization: A, cookies: C, query: Option<&'a str>) -> Result<Jwt, BearerError>
+    where
+        V: TokenVerifier + ?Sized,
+        A: IntoIterator<Item = &'a str>,
+        C: IntoIterator<Item = &'a str>,
+    {
+        let token = self.extract_token(authorization, cookies, query)?;
+
+        Ok(verifier.verify_token(&token)?)
+    }
+
+    /// Value of the `WWW-Authenticate` header for `error`, if its status calls for one
+    pub fn www_authenticate(&self, error: &BearerError) -> Option<String> {
+        error.www_authenticate(self.realm())
+    }
+}
+
+/// Tokens of `Authorization: Bearer` headers. Other schemes are ignored.
+fn header_tokens<'a>(authorization: &[&'a str]) -> Result<Vec<&'a str>, BearerError> {
+    let mut tokens = vec![];
+
+    for value in authorization {
+        let value = value.trim();
+
+        let (scheme, token) = match value.find(' ') {
+            Some(index) => (&value[..index], value[index..].trim_start()),
+            None => (value, ""),
+        };
+
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            continue;
+        }
+
+        if token.is_empty() || !token.bytes().all(is_b64token) {
+            return Err(BearerError::invalid_request("Malformed Authorization header"));
+        }
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// `b64token` characters of RFC 6750
+fn is_b64token(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"-._~+/=".contains(&c)
+}
+
+fn cookie_tokens<'a, 'n>(cookies: &'n [&'a str], name: &'n str) -> impl Iterator<Item = &'a str> + 'n {
+    cookies.iter().flat_map(|header| header.split(';')).filter_map(move |pair| {
+        let (key, value) = split_pair(pair.trim());
+        if key == name {
+            Some(value.trim_matches('"'))
+        } else {
+            None
+        }
+    })
+}
+
+fn query_tokens<'a>(query: Option<&'a str>, name: &str) -> Vec<Cow<'a, str>> {
+    query
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .filter_map(|pair| {
+            let (key, value) = split_pair(pair);
+            if percent_decode(key) == name {
+                Some(percent_decode(value))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn split_pair(pair: &str) -> (&str, &str) {
+    match pair.find('=') {
+        Some(index) => (&pair[..index], &pair[index + 1..]),
+        None => (pair, ""),
+    }
+}
+
+/// Decode `%XX` escapes and `+` of a query string component. Invalid escapes are kept as is.
+fn percent_decode(value: &str) -> Cow<'_, str> {
+    if !value.contains(['%', '+']) {
+        return Cow::Borrowed(value);
+    }
+
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let escaped = match (bytes[index], bytes.get(index + 1).and_then(hex_digit), bytes.get(index + 2).and_then(hex_digit)) {
+            (b'%', Some(high), Some(low)) => Some(high << 4 | low),
+            _ => None,
+        };
+
+        match (bytes[index], escaped) {
+            (_, Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (b'+', None) => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            (byte, None) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+fn hex_digit(c: &u8) -> Option<u8> {
+    (*c as char).to_digit(16).map(|digit| digit as u8)
+}
+
+/// Rejection of a request by bearer authentication, with the HTTP status and RFC 6750 error code
+#[derive(Debug, Clone, PartialEq)]
+pub struct BearerError {
+    status: u16,
+    code: Option<&'static str>,
+    description: Option<&'static str>,
+    scope: Option<String>,
+    error: Option<Box<Error>>,
+}
+
+impl BearerError {
+    /// The request has no token: 401 without error code
+    pub fn missing() -> BearerError {
+        BearerError {
+            status: 401,
+            code: None,
+            description: None,
+            scope: None,
+            error: None,
+        }
+    }
+
+    /// The request is malformed (ie: more than one token): 400 `invalid_request`
+    pub fn invalid_request(description: &'static str) -> BearerError {
+        BearerError {
+            status: 400,
+            code: Some("invalid_request"),
+            description: Some(description),
+            scope: None,
+            error: None,
+        }
+    }
+
+    /// The token does not grant access to the resource: 403 `insufficient_scope`
+    pub fn insufficient_scope(scope: &str) -> BearerError {
+        BearerError {
+            status: 403,
+            code: Some("insufficient_scope"),
+            description: None,
+            scope: Some(scope.to_string()),
+            error: None,
+        }
+    }
+
+    /// HTTP status of the response
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// RFC 6750 error code (`invalid_request`, `invalid_token` or `insufficient_scope`)
+    pub fn code(&self) -> Option<&str> {
+        self.code
+    }
+
+    /// Reason of the rejection sent to the client (`error_description`). It only depends on the type
+    /// of a verification error: see `error` for the details.
+    pub fn description(&self) -> Option<&str> {
+        self.description
+    }
+
+    /// Verification error, if the token was rejected by the key store. Its message and context are
+    /// meant for logs, not for clients.
+    pub fn error(&self) -> Option<&Error> {
+        self.error.as_deref()
+    }
+
+    /// Value of the `WWW-Authenticate` header. `None` for server errors (5xx).
+    pub fn www_authenticate(&self, realm: Option<&str>) -> Option<String> {
+        if self.status >= 500 {
+            return None;
+        }
+
+        let mut parameters = vec![];
+
+        if let Some(realm) = realm {
+            parameters.push(format!("realm=\"{}\"", quoted(realm)));
+        }
+        if let Some(code) = self.code {
+            parameters.push(format!("error=\"{}\"", code));
+        }
+        if let Some(description) = self.description() {
+            parameters.push(format!("error_description=\"{}\"", quoted(description)));
+        }
+        if let Some(scope) = &self.scope {
+            parameters.push(format!("scope=\"{}\"", quoted(scope)));
+        }
+
+        if parameters.is_empty() {
+            Some("Bearer".to_string())
+        } else {
+            Some(format!("Bearer {}", parameters.join(", ")))
+        }
+    }
+}
+
+/// Characters allowed in the quoted values of RFC 6750 challenges
+fn quoted(value: &str) -> String {
+    value.chars().map(|c| if c == '"' || c == '\\' || !(' '..='~').contains(&c) { ' ' } else { c }).collect()
+}
+
+impl From<Error> for BearerError {
+    /// Token problems are 401 `invalid_token`. Key set problems are 503 and internal errors 500.
+    fn from(error: Error) -> Self {
+        let (status, code, description) = match error.typ {
+            Type::Expired => (401, Some("invalid_token"), Some("Token expired")),
+            Type::Early => (401, Some("invalid_token"), Some("Token not yet valid")),
+            Type::Invalid | Type::Certificate | Type::Key | Type::Header | Type::Payload | Type::Signature => (401, Some("invalid_token"), Some("Invalid token")),
+            Type::Connection | Type::Status | Type::Timeout | Type::Tls | Type::Jwks => (503, None, None),
+            Type::Internal => (500, None, None),
+        };
+
+        BearerError {
+            status,
+            code,
+            description,
+            scope: None,
+            error: Some(Box::new(error)),
+        }
+    }
+}
+
+impl Display for BearerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.status)?;
+
+        if let Some(code) = self.code {
+            write!(f, " {}", code)?;
+        }
+        if let Some(description) = self.description() {
+            write!(f, ": {}", description)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for BearerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.as_deref().map(|e| e as &(dyn std::error::Error + 'static))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::bearer::{BearerConfig, BearerError, TokenLocation, TokenVerifier};
+    use crate::error::{err_con, err_exp, err_pay};
+    use crate::jwt::Header;
+    use crate::private_keyset::PrivateKeySet;
+
+    fn config() -> BearerConfig {
+        let mut config = BearerConfig::new();
+        config.set_locations(vec![TokenLocation::Header, TokenLocation::Cookie("session".to_string()), TokenLocation::Query("access_token".to_string())]);
+        config
+    }
+
+    #[test]
+    fn test_extract_token() {
+        let config = config();
+
+        assert_eq!("a.b.c", config.extract_token(vec!["Bearer a.b.c"], vec![], None).unwrap());
+        assert_eq!("a.b.c", config.extract_token(vec!["Basic dXNlcg==", "bearer   a.b.c"], vec![], None).unwrap());
+        assert_eq!("a.b.c", config.extract_token(vec![], vec!["theme=dark; session=a.b.c"], None).unwrap());
+        assert_eq!("a.b+c/=", config.extract_token(vec![], vec![], Some("page=2&access_token=a.b%2Bc%2F%3D")).unwrap());
+
+        assert_eq!(BearerError::missing(), config.extract_token(vec!["Basic dXNlcg=="], vec!["theme=dark"], Some("page=2")).unwrap_err());
+
+        let error = config.extract_token(vec!["Bearer"], vec![], None).unwrap_err();
+        assert_eq!((400, Some("invalid_request")), (error.status(), error.code()));
+
+        let error = config.extract_token(vec!["Bearer a.b.c"], vec![], Some("access_token=a.b.c")).unwrap_err();
+        assert_eq!((400, Some("More than one token in request")), (error.status(), error.description()));
+
+        let error = config.extract_token(vec![], vec![], Some("access_token=")).unwrap_err();
+        assert_eq!(Some("Empty token"), error.description());
+
+        // Locations that are not configured are ignored
+        assert_eq!(BearerError::missing(), BearerConfig::new().extract_token(vec![], vec!["session=a.b.c"], Some("access_token=a.b.c")).unwrap_err());
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let mut keys = PrivateKeySet::new();
+        let kid = keys.generate_ed25519().unwrap();
+        let key_store = keys.key_store().unwrap();
+
+        let token = keys.encode(&kid, &Header::new(json!({})), &json!({ "sub": "user" })).unwrap();
+        let header = format!("Bearer {}", token);
+
+        let config = BearerConfig::new();
+
+        let jwt = config.authenticate(&key_store, vec![header.as_str()], vec![], None).unwrap();
+        assert_eq!(Some("user"), jwt.payload().sub());
+
+        let expired = keys.encode(&kid, &Header::new(json!({})), &json!({ "exp": 1 })).unwrap();
+        let header = format!("Bearer {}", expired);
+
+        let error = config.authenticate(&key_store, vec![header.as_str()], vec![], None).unwrap_err();
+        assert_eq!((401, Some("invalid_token")), (error.status(), error.code()));
+        assert_eq!(Some(&key_store.verify_token(&expired).unwrap_err()), error.error());
+    }
+
+    #[test]
+    fn test_www_authenticate() {
+        let mut config = BearerConfig::new();
+
+        assert_eq!(Some("Bearer".to_string()), config.www_authenticate(&BearerError::missing()));
+
+        config.set_realm("api \"v1\"");
+
+        assert_eq!(Some("Bearer realm=\"api  v1 \"".to_string()), config.www_authenticate(&BearerError::missing()));
+
+        let error = BearerError::from(err_exp("Token expired"));
+        assert_eq!(Some("Bearer realm=\"api  v1 \", error=\"invalid_token\", error_description=\"Token expired\"".to_string()), config.www_authenticate(&error));
+
+        // Details of verification errors are not sent to clients
+        let error = BearerError::from(err_pay("Unknown issuer").with_claim("iss").with_expected("a configured issuer", "https://internal"));
+        assert_eq!(Some("Bearer realm=\"api  v1 \", error=\"invalid_token\", error_description=\"Invalid token\"".to_string()), config.www_authenticate(&error));
+        assert_eq!(Some("Unknown issuer"), error.error().map(|error| error.msg.as_ref()));
+
+        let error = BearerError::insufficient_scope("admin");
+        assert_eq!(403, error.status());
+        assert_eq!(Some("Bearer realm=\"api  v1 \", error=\"insufficient_scope\", scope=\"admin\"".to_string()), config.www_authenticate(&error));
+
+        let error = BearerError::from(err_con("Could not download JWKS"));
+        assert_eq!(503, error.status());
+        assert_eq!(None, config.www_authenticate(&error));
+    }
+}
@@ -0,0 +1,272 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::{ErrorInternalServerError, InternalError};
+use actix_web::http::header::{AUTHORIZATION, COOKIE, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use serde::de::DeserializeOwned;
+
+use crate::bearer::{BearerConfig, BearerError, Claims, TokenVerifier};
+use crate::jwt::Jwt;
+
+type Authorize = Arc<dyn Fn(&Jwt) -> Result<(), BearerError> + Send + Sync>;
+
+/// Bearer token authentication for actix-web (`actix` feature).
+///
+/// Register it as app data (`App::app_data(web::Data::new(auth))`) for the `Jwt` and `Claims<T>`
+/// extractors, and/or wrap services with it (`App::wrap(auth)`) to reject requests without a valid
+/// token before they reach handlers. Rejected requests get the status and `WWW-Authenticate` header
+/// of RFC 6750.
+#[derive(Clone)]
+pub struct BearerAuth {
+    verifier: Arc<dyn TokenVerifier>,
+    config: Arc<BearerConfig>,
+    authorize: Option<Authorize>,
+}
+
+impl BearerAuth {
+    /// Verify tokens with `verifier` (ie: a `SharedKeyStore`), read from the `Authorization` header
+    pub fn new<V: TokenVerifier + 'static>(verifier: Arc<V>) -> BearerAuth {
+        BearerAuth {
+            verifier,
+            config: Arc::new(BearerConfig::new()),
+            authorize: None,
+        }
+    }
+
+    /// Where to find tokens and the realm of challenges
+    pub fn with_config(mut self, config: BearerConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Check verified tokens, for example their scopes. Return `BearerError::insufficient_scope`
+    /// to reject the request with 403.
+    pub fn with_authorize<F>(mut self, authorize: F) -> Self
+    where
+        F: Fn(&Jwt) -> Result<(), BearerError> + Send + Sync + 'static,
+    {
+        self.authorize = Some(Arc::new(authorize));
+        self
+    }
+
+    fn authenticate(&self, request: &HttpRequest) -> Result<Jwt, actix_web::Error> {
+        let headers = request.headers();
+        let authorization = headers.get_all(AUTHORIZATION).filter_map(|value| value.to_str().ok());
+        let cookies = headers.get_all(COOKIE).filter_map(|value| value.to_str().ok());
+        let query = Some(request.query_string()).filter(|query| !query.is_empty());
+
+        let result = self.config.authenticate(&*self.verifier, authorization, cookies, query).and_then(|jwt| match &self.authorize {
+            Some(authorize) => authorize(&jwt).map(|_| jwt),
+            None => Ok(jwt),
+        });
+
+        result.map_err(|error| self.reject(error))
+    }
+
+    fn reject(&self, error: BearerError) -> actix_web::Error {
+        let mut response = HttpResponse::build(StatusCode::from_u16(error.status()).unwrap_or(StatusCode::UNAUTHORIZED));
+
+        if let Some(challenge) = self.config.www_authenticate(&error) {
+            response.insert_header((WWW_AUTHENTICATE, challenge));
+        }
+
+        InternalError::from_response(error, response.finish()).into()
+    }
+}
+
+/// `BearerAuth` of the middleware that verified the request, or else the `BearerAuth` app data
+fn bearer_auth(request: &HttpRequest) -> Result<BearerAuth, actix_web::Error> {
+    if let Some(auth) = request.extensions().get::<BearerAuth>() {
+        return Ok(auth.clone());
+    }
+
+    match request.app_data::<web::Data<BearerAuth>>() {
+        Some(auth) => Ok(auth.get_ref().clone()),
+        None => request.app_data::<BearerAuth>().cloned().ok_or_else(|| ErrorInternalServerError("BearerAuth is not registered as app data")),
+    }
+}
+
+/// Token verified by the `BearerAuth` middleware, or else verified with the `BearerAuth` app data
+fn authenticated(request: &HttpRequest) -> Result<(Jwt, BearerAuth), actix_web::Error> {
+    let auth = bearer_auth(request)?;
+
+    if let Some(jwt) = request.extensions().get::<Jwt>() {
+        return Ok((jwt.clone(), auth));
+    }
+
+    auth.authenticate(request).map(|jwt| (jwt, auth))
+}
+
+impl FromRequest for Jwt {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Jwt, actix_web::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(authenticated(request).map(|(jwt, _)| jwt))
+    }
+}
+
+impl<T: DeserializeOwned> FromRequest for Claims<T> {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Claims<T>, actix_web::Error>>;
+
+    /// Claims that cannot be deserialized into `T` are rejected as an invalid token
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let result = authenticated(request).and_then(|(jwt, auth)| jwt.payload().into::<T>().map(Claims).map_err(|error| auth.reject(BearerError::from(error))));
+
+        ready(result)
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for BearerAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = BearerAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(BearerAuthMiddleware { service, auth: self.clone() }))
+    }
+}
+
+/// Middleware of `BearerAuth`. Adds the verified `Jwt` and the `BearerAuth` to the request extensions.
+pub struct BearerAuthMiddleware<S> {
+    service: S,
+    auth: BearerAuth,
+}
+
+impl<S, B> Service<ServiceRequest> for BearerAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        match self.auth.authenticate(request.request()) {
+            Ok(jwt) => {
+                request.extensions_mut().insert(jwt);
+                request.extensions_mut().insert(self.auth.clone());
+
+                let response = self.service.call(request);
+                Box::pin(async move { response.await.map(ServiceResponse::map_into_left_body) })
+            }
+            Err(error) => Box::pin(ready(Ok(request.error_response(error).map_into_right_body()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{web, App};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use crate::bearer::actix::BearerAuth;
+    use crate::bearer::{BearerConfig, Claims};
+    use crate::jwt::{Header, Jwt};
+    use crate::private_keyset::PrivateKeySet;
+    use crate::shared::SharedKeyStore;
+
+    #[derive(Deserialize)]
+    struct User {
+        sub: String,
+    }
+
+    async fn subject(jwt: Jwt) -> String {
+        jwt.payload().sub().unwrap_or("").to_string()
+    }
+
+    async fn user(user: Claims<User>) -> String {
+        user.sub.clone()
+    }
+
+    #[test]
+    fn test_actix() {
+        let mut keys = PrivateKeySet::new();
+        let kid = keys.generate_ed25519().unwrap();
+
+        let mut config = BearerConfig::new();
+        config.set_realm("api");
+
+        let auth = BearerAuth::new(Arc::new(SharedKeyStore::new(keys.key_store().unwrap()))).with_config(config);
+
+        let token = keys.encode(&kid, &Header::new(json!({})), &json!({ "sub": "user" })).unwrap();
+        let expired = keys.encode(&kid, &Header::new(json!({})), &json!({ "sub": "user", "exp": 1 })).unwrap();
+        let anonymous = keys.encode(&kid, &Header::new(json!({})), &json!({})).unwrap();
+
+        actix_web::rt::System::new().block_on(async {
+            let app = init_service(
+                App::new()
+                    .app_data(web::Data::new(auth.clone()))
+                    .route("/subject", web::get().to(subject))
+                    .route("/user", web::get().to(user))
+                    .service(web::scope("/protected").wrap(auth.clone()).route("", web::get().to(subject))),
+            )
+            .await;
+
+            // Extractors
+            let request = TestRequest::get().uri("/subject").insert_header((AUTHORIZATION, format!("Bearer {}", token))).to_request();
+            assert_eq!("user", read_body(call_service(&app, request).await).await);
+
+            let request = TestRequest::get().uri("/user").insert_header((AUTHORIZATION, format!("Bearer {}", token))).to_request();
+            assert_eq!("user", read_body(call_service(&app, request).await).await);
+
+            // Claims that cannot be deserialized
+            let request = TestRequest::get().uri("/user").insert_header((AUTHORIZATION, format!("Bearer {}", anonymous))).to_request();
+            let response = call_service(&app, request).await;
+            assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+            assert_eq!(
+                "Bearer realm=\"api\", error=\"invalid_token\", error_description=\"Invalid token\"",
+                response.headers().get(WWW_AUTHENTICATE).unwrap().to_str().unwrap()
+            );
+
+            let response = call_service(&app, TestRequest::get().uri("/subject").to_request()).await;
+            assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+            assert_eq!("Bearer realm=\"api\"", response.headers().get(WWW_AUTHENTICATE).unwrap().to_str().unwrap());
+
+            // Middleware
+            let request = TestRequest::get().uri("/protected").insert_header((AUTHORIZATION, format!("Bearer {}", token))).to_request();
+            assert_eq!("user", read_body(call_service(&app, request).await).await);
+
+            let request = TestRequest::get().uri("/protected").insert_header((AUTHORIZATION, format!("Bearer {}", expired))).to_request();
+            let response = call_service(&app, request).await;
+            assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+            assert_eq!(
+                "Bearer realm=\"api\", error=\"invalid_token\", error_description=\"Token expired\"",
+                response.headers().get(WWW_AUTHENTICATE).unwrap().to_str().unwrap()
+            );
+
+            let request = TestRequest::get().uri("/protected").insert_header((AUTHORIZATION, "Bearer a b")).to_request();
+            assert_eq!(StatusCode::BAD_REQUEST, call_service(&app, request).await.status());
+
+            // Not registered as app data
+            let app = init_service(App::new().route("/subject", web::get().to(subject))).await;
+            let request = TestRequest::get().uri("/subject").insert_header((AUTHORIZATION, format!("Bearer {}", token))).to_request();
+            assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, call_service(&app, request).await.status());
+        });
+    }
+}
@@ -0,0 +1,255 @@
+use std::sync::{Arc, Mutex};
+
+use rocket::fairing::{self, Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::{async_trait, Build, Response, Rocket};
+use serde::de::DeserializeOwned;
+
+use crate::bearer::{BearerConfig, BearerError, Claims, TokenVerifier};
+use crate::error::err_int;
+use crate::jwt::Jwt;
+
+type Authorize = Arc<dyn Fn(&Jwt) -> Result<(), BearerError> + Send + Sync>;
+
+/// Bearer token authentication for Rocket (`rocket` feature).
+///
+/// Attach it (`rocket::build().attach(auth)`) to manage it as state for the `Jwt` and `Claims<T>`
+/// request guards and to add the `WWW-Authenticate` header of RFC 6750 to rejected requests.
+/// Managing it as state (`rocket::build().manage(auth)`) is enough for the guards alone.
+#[derive(Clone)]
+pub struct BearerAuth {
+    verifier: Arc<dyn TokenVerifier>,
+    config: Arc<BearerConfig>,
+    authorize: Option<Authorize>,
+}
+
+impl BearerAuth {
+    /// Verify tokens with `verifier` (ie: a `SharedKeyStore`), read from the `Authorization` header
+    pub fn new<V: TokenVerifier + 'static>(verifier: Arc<V>) -> BearerAuth {
+        BearerAuth {
+            verifier,
+            config: Arc::new(BearerConfig::new()),
+            authorize: None,
+        }
+    }
+
+    /// Where to find tokens and the realm of challenges
+    pub fn with_config(mut self, config: BearerConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Check verified tokens, for example their scopes. Return `BearerError::insufficient_scope`
+    /// to reject the request with 403.
+    pub fn with_authorize<F>(mut self, authorize: F) -> Self
+    where
+        F: Fn(&Jwt) -> Result<(), BearerError> + Send + Sync + 'static,
+    {
+        self.authorize = Some(Arc::new(authorize));
+        self
+    }
+
+    fn authenticate(&self, request: &Request<'_>) -> Result<Jwt, BearerError> {
+        let headers = request.headers();
+        let query = request.uri().query().map(|query| query.as_str());
+
+        let jwt = self.config.authenticate(&*self.verifier, headers.get("Authorization"), headers.get("Cookie"), query)?;
+
+        if let Some(authorize) = &self.authorize {
+            authorize(&jwt)?;
+        }
+
+        Ok(jwt)
+    }
+}
+
+/// Outcome of the authentication of a request, cached so that guards verify the token only once
+struct Authentication(Option<Result<Jwt, BearerError>>);
+
+/// Rejection of claims that could not be deserialized by a `Claims<T>` guard
+struct ClaimsRejection(Mutex<Option<BearerError>>);
+
+fn authenticated(request: &Request<'_>) -> Result<Jwt, BearerError> {
+    let authentication = request.local_cache(|| {
+        let result = match request.rocket().state::<BearerAuth>() {
+            Some(auth) => auth.authenticate(request),
+            None => Err(BearerError::from(err_int("BearerAuth is not managed state"))),
+        };
+
+        Authentication(Some(result))
+    });
+
+    match &authentication.0 {
+        Some(result) => result.clone(),
+        None => Err(BearerError::missing()),
+    }
+}
+
+fn outcome<T>(result: Result<T, BearerError>) -> Outcome<T, BearerError> {
+    match result {
+        Ok(value) => Outcome::Success(value),
+        Err(error) => Outcome::Error((Status::from_code(error.status()).unwrap_or(Status::Unauthorized), error)),
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for Jwt {
+    type Error = BearerError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        outcome(authenticated(request))
+    }
+}
+
+#[async_trait]
+impl<'r, T: DeserializeOwned> FromRequest<'r> for Claims<T> {
+    type Error = BearerError;
+
+    /// Claims that cannot be deserialized into `T` are rejected as an invalid token
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let result = authenticated(request).and_then(|jwt| match jwt.payload().into::<T>() {
+            Ok(claims) => Ok(Claims(claims)),
+            Err(error) => {
+                let error = BearerError::from(error);
+
+                if let Ok(mut rejection) = request.local_cache(|| ClaimsRejection(Mutex::new(None))).0.lock() {
+                    *rejection = Some(error.clone());
+                }
+
+                Err(error)
+            }
+        });
+
+        outcome(result)
+    }
+}
+
+#[async_trait]
+impl Fairing for BearerAuth {
+    fn info(&self) -> Info {
+        Info {
+            name: "Bearer authentication",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        Ok(rocket.manage(self.clone()))
+    }
+
+    /// Add the challenge of a guard's rejection, unless the response is not the rejection
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let error = match request.local_cache(|| Authentication(None)) {
+            Authentication(Some(Err(error))) => Some(error.clone()),
+            _ => request.local_cache(|| ClaimsRejection(Mutex::new(None))).0.lock().ok().and_then(|rejection| rejection.clone()),
+        };
+
+        if let Some(error) = error {
+            if response.status().code != error.status() || response.headers().contains("WWW-Authenticate") {
+                return;
+            }
+
+            if let Some(challenge) = self.config.www_authenticate(&error) {
+                response.set_header(Header::new("WWW-Authenticate", challenge));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ::rocket::http::{Header, Status};
+    use ::rocket::local::blocking::Client;
+    use ::rocket::{get, routes};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use crate::bearer::rocket::BearerAuth;
+    use crate::bearer::{BearerConfig, BearerError, Claims};
+    use crate::jwt::{self, Jwt};
+    use crate::private_keyset::PrivateKeySet;
+    use crate::shared::SharedKeyStore;
+
+    #[derive(Deserialize)]
+    struct User {
+        sub: String,
+    }
+
+    #[get("/subject")]
+    fn subject(jwt: Jwt) -> String {
+        jwt.payload().sub().unwrap_or("").to_string()
+    }
+
+    #[get("/user")]
+    fn user(user: Claims<User>) -> String {
+        user.sub.clone()
+    }
+
+    #[get("/optional")]
+    fn optional(jwt: Result<Jwt, BearerError>) -> String {
+        match jwt {
+            Ok(jwt) => jwt.payload().sub().unwrap_or("").to_string(),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    fn bearer(token: &str) -> Header<'static> {
+        Header::new("Authorization", format!("Bearer {}", token))
+    }
+
+    #[test]
+    fn test_rocket() {
+        let mut keys = PrivateKeySet::new();
+        let kid = keys.generate_ed25519().unwrap();
+
+        let mut config = BearerConfig::new();
+        config.set_realm("api");
+
+        let auth = BearerAuth::new(Arc::new(SharedKeyStore::new(keys.key_store().unwrap()))).with_config(config);
+
+        let token = keys.encode(&kid, &jwt::Header::new(json!({})), &json!({ "sub": "user" })).unwrap();
+        let expired = keys.encode(&kid, &jwt::Header::new(json!({})), &json!({ "sub": "user", "exp": 1 })).unwrap();
+        let anonymous = keys.encode(&kid, &jwt::Header::new(json!({})), &json!({})).unwrap();
+
+        let client = Client::untracked(::rocket::build().attach(auth.clone()).mount("/", routes![subject, user, optional])).unwrap();
+
+        assert_eq!("user", client.get("/subject").header(bearer(&token)).dispatch().into_string().unwrap());
+        assert_eq!("user", client.get("/user").header(bearer(&token)).dispatch().into_string().unwrap());
+
+        // Claims that cannot be deserialized
+        let response = client.get("/user").header(bearer(&anonymous)).dispatch();
+        assert_eq!(Status::Unauthorized, response.status());
+        assert_eq!(Some("Bearer realm=\"api\", error=\"invalid_token\", error_description=\"Invalid token\""), response.headers().get_one("WWW-Authenticate"));
+
+        let response = client.get("/subject").dispatch();
+        assert_eq!(Status::Unauthorized, response.status());
+        assert_eq!(Some("Bearer realm=\"api\""), response.headers().get_one("WWW-Authenticate"));
+
+        let response = client.get("/subject").header(bearer(&expired)).dispatch();
+        assert_eq!(Status::Unauthorized, response.status());
+        assert_eq!(
+            Some("Bearer realm=\"api\", error=\"invalid_token\", error_description=\"Token expired\""),
+            response.headers().get_one("WWW-Authenticate")
+        );
+
+        assert_eq!(Status::BadRequest, client.get("/subject").header(bearer("a b")).dispatch().status());
+
+        // Handled by the route: no challenge
+        let response = client.get("/optional").dispatch();
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(None, response.headers().get_one("WWW-Authenticate"));
+        assert_eq!("401", response.into_string().unwrap());
+
+        // Managed state only
+        let client = Client::untracked(::rocket::build().manage(auth).mount("/", routes![subject])).unwrap();
+        assert_eq!("user", client.get("/subject").header(bearer(&token)).dispatch().into_string().unwrap());
+        assert_eq!(Status::Unauthorized, client.get("/subject").dispatch().status());
+
+        // Not managed
+        let client = Client::untracked(::rocket::build().mount("/", routes![subject])).unwrap();
+        assert_eq!(Status::InternalServerError, client.get("/subject").header(bearer(&token)).dispatch().status());
+    }
+}
@@ -0,0 +1,238 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::header::{AUTHORIZATION, COOKIE, WWW_AUTHENTICATE};
+use http::{HeaderValue, Request, Response, StatusCode};
+use pin_project_lite::pin_project;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::bearer::{BearerConfig, BearerError, TokenVerifier};
+use crate::jwt::Jwt;
+
+type Authorize = Arc<dyn Fn(&Jwt) -> Result<(), BearerError> + Send + Sync>;
+
+/// `tower::Layer` that authenticates requests with a bearer token (`tower` feature).
+///
+/// The verified `Jwt` is added to the request extensions (ie: axum's `Extension<Jwt>`). Rejected
+/// requests get an empty response with the status and `WWW-Authenticate` header of RFC 6750, and
+/// the `BearerError` in the response extensions.
+pub struct BearerAuthLayer<V: ?Sized> {
+    verifier: Arc<V>,
+    config: Arc<BearerConfig>,
+    authorize: Option<Authorize>,
+}
+
+impl<V: ?Sized> Clone for BearerAuthLayer<V> {
+    fn clone(&self) -> Self {
+        BearerAuthLayer {
+            verifier: self.verifier.clone(),
+            config: self.config.clone(),
+            authorize: self.authorize.clone(),
+        }
+    }
+}
+
+impl<V: TokenVerifier + ?Sized> BearerAuthLayer<V> {
+    /// Verify tokens with `verifier` (ie: a `SharedKeyStore`), read from the `Authorization` header
+    pub fn new(verifier: Arc<V>) -> BearerAuthLayer<V> {
+        BearerAuthLayer {
+            verifier,
+            config: Arc::new(BearerConfig::new()),
+            authorize: None,
+        }
+    }
+
+    /// Where to find tokens and the realm of challenges
+    pub fn with_config(mut self, config: BearerConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Check verified tokens, for example their scopes. Return `BearerError::insufficient_scope`
+    /// to reject the request with 403.
+    pub fn with_authorize<F>(mut self, authorize: F) -> Self
+    where
+        F: Fn(&Jwt) -> Result<(), BearerError> + Send + Sync + 'static,
+    {
+        self.authorize = Some(Arc::new(authorize));
+        self
+    }
+}
+
+impl<S, V: ?Sized> Layer<S> for BearerAuthLayer<V> {
+    type Service = BearerAuth<S, V>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BearerAuth { inner, layer: self.clone() }
+    }
+}
+
+/// Service of `BearerAuthLayer`
+pub struct BearerAuth<S, V: ?Sized> {
+    inner: S,
+    layer: BearerAuthLayer<V>,
+}
+
+impl<S: Clone, V: ?Sized> Clone for BearerAuth<S, V> {
+    fn clone(&self) -> Self {
+        BearerAuth {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S, V: TokenVerifier + ?Sized> BearerAuth<S, V> {
+    fn authenticate<B>(&self, request: &Request<B>) -> Result<Jwt, BearerError> {
+        let headers = request.headers();
+        let authorization = headers.get_all(AUTHORIZATION).iter().filter_map(|value| value.to_str().ok());
+        let cookies = headers.get_all(COOKIE).iter().filter_map(|value| value.to_str().ok());
+
+        let jwt = self.layer.config.authenticate(&*self.layer.verifier, authorization, cookies, request.uri().query())?;
+
+        if let Some(authorize) = &self.layer.authorize {
+            authorize(&jwt)?;
+        }
+
+        Ok(jwt)
+    }
+
+    fn reject<B: Default>(&self, error: BearerError) -> Response<B> {
+        let mut response = Response::new(B::default());
+
+        *response.status_mut() = StatusCode::from_u16(error.status()).unwrap_or(StatusCode::UNAUTHORIZED);
+
+        if let Some(challenge) = self.layer.config.www_authenticate(&error) {
+            if let Ok(value) = HeaderValue::from_str(&challenge) {
+                response.headers_mut().insert(WWW_AUTHENTICATE, value);
+            }
+        }
+
+        response.extensions_mut().insert(error);
+
+        response
+    }
+}
+
+impl<S, V, ReqBody, ResBody> Service<Request<ReqBody>> for BearerAuth<S, V>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    V: TokenVerifier + ?Sized,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, Response<ResBody>, S::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        match self.authenticate(&request) {
+            Ok(jwt) => {
+                request.extensions_mut().insert(jwt);
+                ResponseFuture::Authorized { future: self.inner.call(request) }
+            }
+            Err(error) => ResponseFuture::Rejected { response: ready(Ok(self.reject(error))) },
+        }
+    }
+}
+
+pin_project! {
+    /// Response future of `BearerAuth`
+    #[project = ResponseFutureProj]
+    pub enum ResponseFuture<F, R, E> {
+        Authorized { #[pin] future: F },
+        Rejected { #[pin] response: Ready<Result<R, E>> },
+    }
+}
+
+impl<F, R, E> Future for ResponseFuture<F, R, E>
+where
+    F: Future<Output = Result<R, E>>,
+{
+    type Output = Result<R, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Authorized { future } => future.poll(cx),
+            ResponseFutureProj::Rejected { response } => response.poll(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::sync::Arc;
+
+    use ::tower::{service_fn, Layer, ServiceExt};
+    use http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+    use http::{Request, Response, StatusCode};
+    use serde_json::json;
+
+    use crate::bearer::tower::BearerAuthLayer;
+    use crate::bearer::{BearerConfig, BearerError, TokenLocation};
+    use crate::jwt::{Header, Jwt};
+    use crate::private_keyset::PrivateKeySet;
+    use crate::shared::SharedKeyStore;
+
+    fn call(layer: &BearerAuthLayer<SharedKeyStore>, request: Request<String>) -> Response<String> {
+        let service = layer.layer(service_fn(|request: Request<String>| async move {
+            let sub = request.extensions().get::<Jwt>().and_then(|jwt| jwt.payload().sub()).unwrap_or("").to_string();
+            Ok::<_, Infallible>(Response::new(sub))
+        }));
+
+        tokio_test::block_on(service.oneshot(request)).unwrap()
+    }
+
+    #[test]
+    fn test_layer() {
+        let mut keys = PrivateKeySet::new();
+        let kid = keys.generate_ec("ES256").unwrap();
+        let key_store = Arc::new(SharedKeyStore::new(keys.key_store().unwrap()));
+
+        let mut config = BearerConfig::new();
+        config.set_realm("api");
+        config.set_locations(vec![TokenLocation::Header, TokenLocation::Query("access_token".to_string())]);
+
+        let layer = BearerAuthLayer::new(key_store).with_config(config).with_authorize(|jwt| match jwt.payload().get_str("scope") {
+            Some("admin") => Ok(()),
+            _ => Err(BearerError::insufficient_scope("admin")),
+        });
+
+        let token = keys.encode(&kid, &Header::new(json!({})), &json!({ "sub": "user", "scope": "admin" })).unwrap();
+
+        let request = Request::builder().header(AUTHORIZATION, format!("Bearer {}", token)).body(String::new()).unwrap();
+        let response = call(&layer, request);
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("user", response.body());
+
+        let request = Request::builder().uri(format!("/?access_token={}", token)).body(String::new()).unwrap();
+        assert_eq!("user", call(&layer, request).body());
+
+        let response = call(&layer, Request::new(String::new()));
+        assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+        assert_eq!("Bearer realm=\"api\"", response.headers()[WWW_AUTHENTICATE]);
+        assert_eq!(Some(&BearerError::missing()), response.extensions().get::<BearerError>());
+
+        let expired = keys.encode(&kid, &Header::new(json!({})), &json!({ "scope": "admin", "exp": 1 })).unwrap();
+        let request = Request::builder().header(AUTHORIZATION, format!("Bearer {}", expired)).body(String::new()).unwrap();
+        let response = call(&layer, request);
+        assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+        assert_eq!("Bearer realm=\"api\", error=\"invalid_token\", error_description=\"Token expired\"", response.headers()[WWW_AUTHENTICATE]);
+
+        let user = keys.encode(&kid, &Header::new(json!({})), &json!({ "sub": "user" })).unwrap();
+        let request = Request::builder().header(AUTHORIZATION, format!("Bearer {}", user)).body(String::new()).unwrap();
+        let response = call(&layer, request);
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+        assert_eq!("Bearer realm=\"api\", error=\"insufficient_scope\", scope=\"admin\"", response.headers()[WWW_AUTHENTICATE]);
+
+        let request = Request::builder().uri(format!("/?access_token={}", token)).header(AUTHORIZATION, format!("Bearer {}", token)).body(String::new()).unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, call(&layer, request).status());
+    }
+}
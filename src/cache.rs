@@ -0,0 +1,238 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use ring::digest::{Context, SHA256};
+
+pub(crate) type TokenHash = [u8; 32];
+
+/// Hash of a token given its `HEADER.PAYLOAD` body and its signature
+pub(crate) fn token_hash(body: &str, signature: &str) -> TokenHash {
+    let mut context = Context::new(&SHA256);
+
+    context.update(body.as_bytes());
+    context.update(b".");
+    context.update(signature.as_bytes());
+
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(context.finish().as_ref());
+
+    hash
+}
+
+struct Entry {
+    expire_time: SystemTime,
+    last_use: u64,
+}
+
+/// Bounded LRU cache of tokens whose signature was successfully verified
+pub(crate) struct VerifyCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<TokenHash, Entry>,
+    /// Entries ordered from least to most recently used
+    usage: BTreeMap<u64, TokenHash>,
+    tick: u64,
+}
+
+impl VerifyCache {
+    pub(crate) fn new(capacity: usize, ttl: Duration) -> VerifyCache {
+        VerifyCache {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            usage: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// True if the token is in the cache and has not expired at `time`
+    pub(crate) fn contains(&mut self, hash: &TokenHash, time: SystemTime) -> bool {
+        let expired = match self.entries.get(hash) {
+            Some(entry) => entry.expire_time <= time,
+            None => return false,
+        };
+
+        if expired {
+            self.remove(hash);
+            return false;
+        }
+
+        self.touch(hash);
+
+        true
+    }
+
+    /// Remember a verified token until the earlier of `expiry` (the `exp` claim) and the TTL
+    pub(crate) fn insert(&mut self, hash: TokenHash, expiry: Option<SystemTime>, time: SystemTime) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let expire_time = match (time.checked_add(self.ttl), expiry) {
+            (Some(ttl_time), Some(expiry)) => ttl_time.min(expiry),
+            (Some(ttl_time), None) => ttl_time,
+            (None, Some(expiry)) => expiry,
+            (None, None) => return,
+        };
+
+        if expire_time <= time {
+            return;
+        }
+
+        self.remove(&hash);
+
+        while self.entries.len() >= self.capacity {
+            let oldest = match self.usage.iter().next() {
+                Some((_, hash)) => *hash,
+                None => break,
+            };
+            self.remove(&oldest);
+        }
+
+        self.tick += 1;
+        self.usage.insert(self.tick, hash);
+        self.entries.insert(hash, Entry { expire_time, last_use: self.tick });
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn touch(&mut self, hash: &TokenHash) {
+        self.tick += 1;
+
+        if let Some(entry) = self.entries.get_mut(hash) {
+            self.usage.remove(&entry.last_use);
+            entry.last_use = self.tick;
+            self.usage.insert(self.tick, *hash);
+        }
+    }
+
+    fn remove(&mut self, hash: &TokenHash) {
+        if let Some(entry) = self.entries.remove(hash) {
+            self.usage.remove(&entry.last_use);
+        }
+    }
+}
+
+/// `VerifyCache` shared by the verifications of a key store and of its clones (ie: the snapshots
+/// of a `SharedKeyStore`). A store whose keys change must switch to a `renewed` cache: its clones
+/// keep the entries verified with their own keys.
+#[derive(Clone)]
+pub(crate) struct LockedCache {
+    cache: Arc<Mutex<VerifyCache>>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl LockedCache {
+    pub(crate) fn new(capacity: usize, ttl: Duration) -> LockedCache {
+        LockedCache {
+            cache: Arc::new(Mutex::new(VerifyCache::new(capacity, ttl))),
+            capacity,
+            ttl,
+        }
+    }
+
+    /// Empty cache with the same capacity and TTL, not shared with this one
+    pub(crate) fn renewed(&self) -> LockedCache {
+        LockedCache::new(self.capacity, self.ttl)
+    }
+}
+
+impl Deref for LockedCache {
+    type Target = Mutex<VerifyCache>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use crate::cache::{token_hash, LockedCache, VerifyCache};
+
+    fn time(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::new(secs, 0)
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(token_hash("a.b", "c"), token_hash("a.b", "c"));
+        assert_ne!(token_hash("a.b", "c"), token_hash("a.b", "d"));
+    }
+
+    #[test]
+    fn test_ttl_and_exp() {
+        let mut cache = VerifyCache::new(10, Duration::new(60, 0));
+
+        let ttl_bound = token_hash("a.b", "1");
+        let exp_bound = token_hash("a.b", "2");
+
+        cache.insert(ttl_bound, None, time(100));
+        cache.insert(exp_bound, Some(time(130)), time(100));
+
+        assert!(cache.contains(&ttl_bound, time(159)));
+        assert!(cache.contains(&exp_bound, time(129)));
+
+        assert!(!cache.contains(&exp_bound, time(130)));
+        assert!(!cache.contains(&ttl_bound, time(160)));
+
+        assert_eq!(0, cache.len());
+    }
+
+    #[test]
+    fn test_expired_token_not_cached() {
+        let mut cache = VerifyCache::new(10, Duration::new(60, 0));
+
+        let hash = token_hash("a.b", "1");
+
+        cache.insert(hash, Some(time(90)), time(100));
+
+        assert_eq!(0, cache.len());
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut cache = VerifyCache::new(2, Duration::new(60, 0));
+
+        let first = token_hash("a.b", "1");
+        let second = token_hash("a.b", "2");
+        let third = token_hash("a.b", "3");
+
+        cache.insert(first, None, time(100));
+        cache.insert(second, None, time(100));
+
+        // Using `first` makes `second` the least recently used
+        assert!(cache.contains(&first, time(101)));
+
+        cache.insert(third, None, time(102));
+
+        assert_eq!(2, cache.len());
+        assert!(cache.contains(&first, time(103)));
+        assert!(!cache.contains(&second, time(103)));
+        assert!(cache.contains(&third, time(103)));
+    }
+
+    #[test]
+    fn test_shared_by_clones() {
+        let cache = LockedCache::new(10, Duration::new(60, 0));
+        let clone = cache.clone();
+
+        let hash = token_hash("a.b", "1");
+
+        clone.lock().unwrap().insert(hash, None, time(100));
+
+        assert!(cache.lock().unwrap().contains(&hash, time(101)));
+
+        let renewed = cache.renewed();
+
+        assert!(!renewed.lock().unwrap().contains(&hash, time(101)));
+        assert!(clone.lock().unwrap().contains(&hash, time(101)));
+    }
+}
@@ -0,0 +1,323 @@
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use ring::hmac;
+use ring::rand::SystemRandom;
+use ring::signature::{
+    EcdsaKeyPair, EcdsaSigningAlgorithm, Ed25519KeyPair, KeyPair, RsaEncoding, RsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING, ECDSA_P384_SHA384_FIXED_SIGNING, RSA_PKCS1_SHA256, RSA_PKCS1_SHA384, RSA_PKCS1_SHA512, RSA_PSS_SHA256, RSA_PSS_SHA384,
+    RSA_PSS_SHA512,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::*;
+use crate::jwt::Header;
+use crate::keyset::JwtKey;
+use crate::pem::decode_pem;
+
+#[derive(Debug)]
+enum PrivateKey {
+    Rsa(RsaKeyPair, &'static dyn RsaEncoding),
+    Ecdsa(EcdsaKeyPair),
+    Ed25519(Ed25519KeyPair),
+    Hmac(hmac::Key),
+}
+
+/// Private (or secret) key used to sign tokens
+///
+/// Supported algorithms are `RS256`, `RS384`, `RS512`, `PS256`, `PS384`, `PS512`, `ES256`, `ES384`,
+/// `EdDSA` (Ed25519), `HS256`, `HS384` and `HS512`.
+#[derive(Debug)]
+pub struct SigningKey {
+    alg: &'static str,
+    kid: Option<String>,
+    key: PrivateKey,
+}
+
+impl SigningKey {
+    /// Load an asymmetric private key from PEM.
+    ///
+    /// `PRIVATE KEY` (PKCS#8) is accepted for every asymmetric algorithm. `RSA PRIVATE KEY` (PKCS#1)
+    /// is also accepted for RSA algorithms.
+    pub fn from_pem(alg: &str, pem: &str) -> Result<SigningKey, Error> {
+        let (label, der) = decode_pem(pem)?;
+
+        match label.as_str() {
+            "PRIVATE KEY" => SigningKey::from_pkcs8(alg, &der),
+            "RSA PRIVATE KEY" => SigningKey::from_rsa_der(alg, &der),
+            _ => Err(err_key("Unsupported PEM type").with_expected("PRIVATE KEY", label)),
+        }
+    }
+
+    /// Load an asymmetric private key from PKCS#8 DER
+    pub fn from_pkcs8(alg: &str, der: &[u8]) -> Result<SigningKey, Error> {
+        let (alg, key) = if let Some((alg, encoding)) = rsa_encoding(alg) {
+            let key_pair = RsaKeyPair::from_pkcs8(der).map_err(|e| err_key("Invalid RSA private key").with_source(e))?;
+            (alg, PrivateKey::Rsa(key_pair, encoding))
+        } else if let Some((alg, signing)) = ecdsa_signing(alg) {
+            let key_pair = EcdsaKeyPair::from_pkcs8(signing, der).map_err(|e| err_key("Invalid EC private key").with_source(e))?;
+            (alg, PrivateKey::Ecdsa(key_pair))
+        } else if alg == "EdDSA" {
+            let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der).map_err(|e| err_key("Invalid Ed25519 private key").with_source(e))?;
+            ("EdDSA", PrivateKey::Ed25519(key_pair))
+        } else {
+            return Err(err_key("Unsupported algorithm").with_alg(alg));
+        };
+
+        Ok(SigningKey { alg, kid: None, key })
+    }
+
+    /// Load an RSA private key from PKCS#1 DER
+    pub fn from_rsa_der(alg: &str, der: &[u8]) -> Result<SigningKey, Error> {
+        let (alg, encoding) = rsa_encoding(alg).ok_or_else(|| err_key("Unsupported algorithm").with_alg(alg))?;
+
+        let key_pair = RsaKeyPair::from_der(der).map_err(|e| err_key("Invalid RSA private key").with_source(e))?;
+
+        Ok(SigningKey {
+            alg,
+            kid: None,
+            key: PrivateKey::Rsa(key_pair, encoding),
+        })
+    }
+
+    /// Create an HMAC key from a shared secret. The secret must not be empty.
+    pub fn from_secret(alg: &str, secret: &[u8]) -> Result<SigningKey, Error> {
+        let (alg, algorithm) = match alg {
+            "HS256" => ("HS256", hmac::HMAC_SHA256),
+            "HS384" => ("HS384", hmac::HMAC_SHA384),
+            "HS512" => ("HS512", hmac::HMAC_SHA512),
+            _ => return Err(err_key("Unsupported algorithm").with_alg(alg)),
+        };
+
+        if secret.is_empty() {
+            return Err(err_key("Empty HMAC secret").with_alg(alg));
+        }
+
+        Ok(SigningKey {
+            alg,
+            kid: None,
+            key: PrivateKey::Hmac(hmac::Key::new(algorithm, secret)),
+        })
+    }
+
+    /// Set the key id added to the header of signed tokens
+    pub fn with_kid(mut self, kid: &str) -> SigningKey {
+        self.kid = Some(kid.to_string());
+        self
+    }
+
+    pub fn alg(&self) -> &str {
+        self.alg
+    }
+
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    /// Public half of the key as a JWK, using the key's `kid` (empty if none).
+    ///
+    /// None for HMAC keys, which have no public half.
+    pub fn public_key(&self) -> Option<JwtKey> {
+        let mut key = JwtKey::new(self.kid.as_deref().unwrap_or(""), "", "");
+
+        key.alg = Some(self.alg.to_string());
+        key.key_use = Some("sig".to_string());
+
+        match &self.key {
+            PrivateKey::Rsa(key_pair, _) => {
+                let public_key = key_pair.public_key();
+                key.kty = "RSA".to_string();
+                key.n = encode_config(public_key.modulus().big_endian_without_leading_zero(), URL_SAFE_NO_PAD);
+                key.e = encode_config(public_key.exponent().big_endian_without_leading_zero(), URL_SAFE_NO_PAD);
+            }
+            PrivateKey::Ecdsa(key_pair) => {
+                // Uncompressed point: 0x04 || x || y
+                let point = key_pair.public_key().as_ref();
+                let size = (point.len() - 1) / 2;
+                key.kty = "EC".to_string();
+                key.crv = if self.alg == "ES256" { "P-256" } else { "P-384" }.to_string();
+                key.x = encode_config(&point[1..1 + size], URL_SAFE_NO_PAD);
+                key.y = encode_config(&point[1 + size..], URL_SAFE_NO_PAD);
+            }
+            PrivateKey::Ed25519(key_pair) => {
+                key.kty = "OKP".to_string();
+                key.crv = "Ed25519".to_string();
+                key.x = encode_config(key_pair.public_key().as_ref(), URL_SAFE_NO_PAD);
+            }
+            PrivateKey::Hmac(_) => return None,
+        }
+
+        Some(key)
+    }
+
+    /// Sign `message` and return the raw signature
+    pub fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        match &self.key {
+            PrivateKey::Rsa(key_pair, encoding) => {
+                let mut signature = vec![0u8; key_pair.public_modulus_len()];
+                key_pair.sign(*encoding, &SystemRandom::new(), message, &mut signature).map_err(|e| err_sig("Failed to sign token").with_source(e))?;
+                Ok(signature)
+            }
+            PrivateKey::Ecdsa(key_pair) => {
+                let signature = key_pair.sign(&SystemRandom::new(), message).map_err(|e| err_sig("Failed to sign token").with_source(e))?;
+                Ok(signature.as_ref().to_vec())
+            }
+            PrivateKey::Ed25519(key_pair) => Ok(key_pair.sign(message).as_ref().to_vec()),
+            PrivateKey::Hmac(key) => Ok(hmac::sign(key, message).as_ref().to_vec()),
+        }
+    }
+}
+
+/// Create a signed token (compact JWS) from a header and claims.
+///
+/// `alg` is set in the header from the signing key, as well as `kid` if the key has one and the
+/// header does not. A header `alg` that differs from the key's algorithm is an error.
+pub fn encode<T: Serialize>(header: &Header, claims: &T, key: &SigningKey) -> Result<String, Error> {
+    let mut header = header.json.clone();
+
+    let fields = header.as_object_mut().ok_or_else(|| err_hea("Header is not a JSON object"))?;
+
+    match fields.get("alg").and_then(Value::as_str) {
+        Some(alg) if alg != key.alg => {
+            return Err(err_hea("Header algorithm does not match signing key").with_expected(key.alg, alg));
+        }
+        _ => {
+            fields.insert("alg".to_string(), Value::from(key.alg));
+        }
+    }
+
+    if let Some(kid) = &key.kid {
+        fields.entry("kid").or_insert_with(|| Value::from(kid.as_str()));
+    }
+
+    let header = serde_json::to_vec(&header).map_err(|e| err_hea("Failed to serialize header").with_source(e))?;
+    let payload = serde_json::to_vec(claims).map_err(|e| err_pay("Failed to serialize payload").with_source(e))?;
+
+    let body = format!("{}.{}", encode_config(&header, URL_SAFE_NO_PAD), encode_config(&payload, URL_SAFE_NO_PAD));
+
+    let signature = key.sign(body.as_bytes())?;
+
+    Ok(format!("{}.{}", body, encode_config(&signature, URL_SAFE_NO_PAD)))
+}
+
+fn rsa_encoding(alg: &str) -> Option<(&'static str, &'static dyn RsaEncoding)> {
+    match alg {
+        "RS256" => Some(("RS256", &RSA_PKCS1_SHA256)),
+        "RS384" => Some(("RS384", &RSA_PKCS1_SHA384)),
+        "RS512" => Some(("RS512", &RSA_PKCS1_SHA512)),
+        "PS256" => Some(("PS256", &RSA_PSS_SHA256)),
+        "PS384" => Some(("PS384", &RSA_PSS_SHA384)),
+        "PS512" => Some(("PS512", &RSA_PSS_SHA512)),
+        _ => None,
+    }
+}
+
+fn ecdsa_signing(alg: &str) -> Option<(&'static str, &'static EcdsaSigningAlgorithm)> {
+    match alg {
+        "ES256" => Some(("ES256", &ECDSA_P256_SHA256_FIXED_SIGNING)),
+        "ES384" => Some(("ES384", &ECDSA_P384_SHA384_FIXED_SIGNING)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{decode_config, URL_SAFE_NO_PAD};
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, UnparsedPublicKey, ECDSA_P256_SHA256_FIXED, ECDSA_P256_SHA256_FIXED_SIGNING, ED25519};
+    use serde_json::json;
+
+    use crate::encoder::{encode, SigningKey};
+    use crate::error::Type;
+    use crate::jwt::Header;
+    use crate::keyset::KeyStore;
+
+    fn signature_parts(token: &str) -> (String, Vec<u8>) {
+        let index = token.rfind('.').unwrap();
+        (token[..index].to_string(), decode_config(&token[index + 1..], URL_SAFE_NO_PAD).unwrap())
+    }
+
+    #[test]
+    fn test_encode_rsa() {
+        let key = SigningKey::from_pem("RS256", include_str!("../test/private.pem")).unwrap().with_kid("1");
+
+        let header = Header::new(json!({ "typ": "JWT" }));
+
+        let token = encode(&header, &json!({ "iss": "https://chronogears.com/test", "exp": 500 }), &key).unwrap();
+
+        let jwt = KeyStore::new().decode(&token).unwrap();
+
+        assert_eq!(Some("RS256"), jwt.header().alg());
+        assert_eq!(Some("1"), jwt.header().kid());
+        assert_eq!(Some("JWT"), jwt.header().typ());
+        assert_eq!(Some("https://chronogears.com/test"), jwt.payload().iss());
+        assert_eq!(Some(500), jwt.payload().exp());
+    }
+
+    #[test]
+    fn test_encode_alg_mismatch() {
+        let key = SigningKey::from_secret("HS256", b"secret").unwrap();
+
+        let header = Header::new(json!({ "alg": "RS256" }));
+
+        let error = encode(&header, &json!({}), &key).unwrap_err();
+
+        assert_eq!(Type::Header, error.typ);
+        assert_eq!(Some("HS256"), error.expected());
+    }
+
+    #[test]
+    fn test_encode_ecdsa() {
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new()).unwrap();
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref()).unwrap();
+
+        let key = SigningKey::from_pkcs8("ES256", pkcs8.as_ref()).unwrap();
+
+        let token = encode(&Header::new(json!({})), &json!({ "sub": "1" }), &key).unwrap();
+
+        let (body, signature) = signature_parts(&token);
+
+        let public_key = UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, key_pair.public_key().as_ref());
+
+        assert!(public_key.verify(body.as_bytes(), &signature).is_ok());
+    }
+
+    #[test]
+    fn test_encode_ed25519() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+
+        let key = SigningKey::from_pkcs8("EdDSA", pkcs8.as_ref()).unwrap();
+
+        let token = encode(&Header::new(json!({})), &json!({ "sub": "1" }), &key).unwrap();
+
+        let (body, signature) = signature_parts(&token);
+
+        let public_key = UnparsedPublicKey::new(&ED25519, key_pair.public_key().as_ref());
+
+        assert!(public_key.verify(body.as_bytes(), &signature).is_ok());
+    }
+
+    #[test]
+    fn test_encode_hmac() {
+        let key = SigningKey::from_secret("HS256", b"secret").unwrap();
+
+        let token = encode(&Header::new(json!({})), &json!({ "sub": "1" }), &key).unwrap();
+
+        let (body, signature) = signature_parts(&token);
+
+        let hmac_key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, b"secret");
+
+        assert!(ring::hmac::verify(&hmac_key, body.as_bytes(), &signature).is_ok());
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert_eq!(Type::Key, SigningKey::from_secret("none", b"").unwrap_err().typ);
+        assert_eq!("Empty HMAC secret", SigningKey::from_secret("HS256", b"").unwrap_err().msg);
+        assert_eq!(Type::Key, SigningKey::from_pem("HS256", include_str!("../test/private.pem")).unwrap_err().typ);
+        assert_eq!(Type::Key, SigningKey::from_pem("RS256", include_str!("../test/public.pem")).unwrap_err().typ);
+
+        let error = SigningKey::from_pem("RS256", include_str!("../test/ec-private.pem")).unwrap_err();
+        assert_eq!("Invalid RSA private key", error.msg);
+        assert!(std::error::Error::source(&error).is_some());
+    }
+}
@@ -1,24 +1,156 @@
+use std::borrow::Cow;
 use std::fmt::{Display, Formatter};
 use std::fmt;
+use std::sync::Arc;
 
-#[derive(Debug, PartialEq)]
+type Source = Arc<dyn std::error::Error + Send + Sync + 'static>;
+
+#[derive(Debug, Clone)]
 pub struct Error {
     /// Debug message associated with error
-    pub msg: &'static str,
+    pub msg: Cow<'static, str>,
     pub typ: Type,
+    context: Option<Box<Context>>,
+    source: Option<Source>,
+}
+
+/// Details about what caused an error
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Context {
+    kid: Option<String>,
+    alg: Option<String>,
+    claim: Option<String>,
+    expected: Option<String>,
+    actual: Option<String>,
+    status: Option<u16>,
+}
+
+impl Error {
+    pub fn new(msg: impl Into<Cow<'static, str>>, typ: Type) -> Error {
+        Error {
+            msg: msg.into(),
+            typ,
+            context: None,
+            source: None,
+        }
+    }
+
+    /// Key id (`kid`) involved in the error, if any
+    pub fn kid(&self) -> Option<&str> {
+        self.context.as_ref()?.kid.as_deref()
+    }
+
+    /// Algorithm (`alg`) involved in the error, if any
+    pub fn alg(&self) -> Option<&str> {
+        self.context.as_ref()?.alg.as_deref()
+    }
+
+    /// Name of the claim that failed validation, if any
+    pub fn claim(&self) -> Option<&str> {
+        self.context.as_ref()?.claim.as_deref()
+    }
+
+    /// Value that was expected, if any
+    pub fn expected(&self) -> Option<&str> {
+        self.context.as_ref()?.expected.as_deref()
+    }
+
+    /// Value that was found instead of the expected value, if any
+    pub fn actual(&self) -> Option<&str> {
+        self.context.as_ref()?.actual.as_deref()
+    }
+
+    /// HTTP status code returned while loading keys, if any
+    pub fn status(&self) -> Option<u16> {
+        self.context.as_ref()?.status
+    }
+
+    pub(crate) fn with_kid(mut self, kid: impl Into<String>) -> Error {
+        self.context_mut().kid = Some(kid.into());
+        self
+    }
+
+    pub(crate) fn with_alg(mut self, alg: impl Into<String>) -> Error {
+        self.context_mut().alg = Some(alg.into());
+        self
+    }
+
+    pub(crate) fn with_claim(mut self, claim: impl Into<String>) -> Error {
+        self.context_mut().claim = Some(claim.into());
+        self
+    }
+
+    pub(crate) fn with_expected(mut self, expected: impl Into<String>, actual: impl Into<String>) -> Error {
+        let context = self.context_mut();
+        context.expected = Some(expected.into());
+        context.actual = Some(actual.into());
+        self
+    }
+
+    pub(crate) fn with_status(mut self, status: u16) -> Error {
+        self.context_mut().status = Some(status);
+        self
+    }
+
+    pub(crate) fn with_source(mut self, source: impl std::error::Error + Send + Sync + 'static) -> Error {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    fn context_mut(&mut self) -> &mut Context {
+        self.context.get_or_insert_with(Default::default)
+    }
+}
+
+impl PartialEq for Error {
+    /// Errors are equal if their message, type and context are equal. The source is ignored.
+    fn eq(&self, other: &Self) -> bool {
+        self.msg == other.msg && self.typ == other.typ && self.context == other.context
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}: {}", self.typ, self.msg)
+        write!(f, "{:?}: {}", self.typ, self.msg)?;
+
+        if let Some(context) = &self.context {
+            let details = [
+                ("kid", &context.kid),
+                ("alg", &context.alg),
+                ("claim", &context.claim),
+                ("expected", &context.expected),
+                ("actual", &context.actual),
+            ];
+
+            let mut separator = " (";
+            for (name, value) in details.iter() {
+                if let Some(value) = value {
+                    write!(f, "{}{}: {}", separator, name, value)?;
+                    separator = ", ";
+                }
+            }
+            if let Some(status) = context.status {
+                write!(f, "{}status: {}", separator, status)?;
+                separator = ", ";
+            }
+            if separator != " (" {
+                write!(f, ")")?;
+            }
+        }
+
+        Ok(())
     }
 }
 
 impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_ref().map(|e| e.as_ref() as &(dyn std::error::Error + 'static))
+    }
 }
 
 /// Type of error encountered
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum Type {
     /// Token is invalid
     /// For example, the format of the token is not "HEADER.PAYLOAD.SIGNATURE"
@@ -33,6 +165,14 @@ pub enum Type {
     Key,
     /// Could not download key set
     Connection,
+    /// Key set server returned a non-success HTTP status. See `Error::status()` for the code
+    Status,
+    /// Timed out while downloading key set
+    Timeout,
+    /// TLS failure while downloading key set (ie: invalid server certificate)
+    Tls,
+    /// Key set is not a valid JWKS document
+    Jwks,
     /// Problem with JWT header
     Header,
     /// Problem with JWT payload
@@ -43,49 +183,95 @@ pub enum Type {
     Internal,
 }
 
-pub(crate) fn err(msg: &'static str, typ: Type) -> Error {
-    Error { msg, typ }
+pub(crate) fn err(msg: impl Into<Cow<'static, str>>, typ: Type) -> Error {
+    Error::new(msg, typ)
 }
 
-pub(crate) fn err_inv(msg: &'static str) -> Error {
+pub(crate) fn err_inv(msg: impl Into<Cow<'static, str>>) -> Error {
     err(msg, Type::Invalid)
 }
 
-pub(crate) fn err_exp(msg: &'static str) -> Error {
+pub(crate) fn err_exp(msg: impl Into<Cow<'static, str>>) -> Error {
     err(msg, Type::Expired)
 }
 
-pub(crate) fn err_nbf(msg: &'static str) -> Error {
+pub(crate) fn err_nbf(msg: impl Into<Cow<'static, str>>) -> Error {
     err(msg, Type::Early)
 }
 
-pub(crate) fn err_cer(msg: &'static str) -> Error {
+pub(crate) fn err_cer(msg: impl Into<Cow<'static, str>>) -> Error {
     err(msg, Type::Certificate)
 }
 
-pub(crate) fn err_key(msg: &'static str) -> Error {
+pub(crate) fn err_key(msg: impl Into<Cow<'static, str>>) -> Error {
     err(msg, Type::Key)
 }
 
-pub(crate) fn err_con(msg: &'static str) -> Error {
+pub(crate) fn err_con(msg: impl Into<Cow<'static, str>>) -> Error {
     err(msg, Type::Connection)
 }
 
-pub(crate) fn err_hea(msg: &'static str) -> Error {
+pub(crate) fn err_sta(msg: impl Into<Cow<'static, str>>) -> Error {
+    err(msg, Type::Status)
+}
+
+pub(crate) fn err_tim(msg: impl Into<Cow<'static, str>>) -> Error {
+    err(msg, Type::Timeout)
+}
+
+pub(crate) fn err_tls(msg: impl Into<Cow<'static, str>>) -> Error {
+    err(msg, Type::Tls)
+}
+
+pub(crate) fn err_jwk(msg: impl Into<Cow<'static, str>>) -> Error {
+    err(msg, Type::Jwks)
+}
+
+pub(crate) fn err_hea(msg: impl Into<Cow<'static, str>>) -> Error {
     err(msg, Type::Header)
 }
 
-pub(crate) fn err_pay(msg: &'static str) -> Error {
+pub(crate) fn err_pay(msg: impl Into<Cow<'static, str>>) -> Error {
     err(msg, Type::Payload)
 }
 
-pub(crate) fn err_sig(msg: &'static str) -> Error {
+pub(crate) fn err_sig(msg: impl Into<Cow<'static, str>>) -> Error {
     err(msg, Type::Signature)
 }
 
-pub(crate) fn err_int(msg: &'static str) -> Error {
+pub(crate) fn err_int(msg: impl Into<Cow<'static, str>>) -> Error {
     err(msg, Type::Internal)
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use std::error::Error as StdError;
+
+    use crate::error::{err_key, err_pay, Type};
+
+    #[test]
+    fn test_display_context() {
+        let error = err_key("JWT key does not exists").with_kid("abc");
+
+        assert_eq!("Key: JWT key does not exists (kid: abc)", error.to_string());
+
+        let error = err_pay("Wrong issuer").with_claim("iss").with_expected("a", "b");
+
+        assert_eq!("Payload: Wrong issuer (claim: iss, expected: a, actual: b)", error.to_string());
+        assert_eq!(Some("iss"), error.claim());
+        assert_eq!(Some("a"), error.expected());
+        assert_eq!(Some("b"), error.actual());
+        assert_eq!(None, error.kid());
+    }
+
+    #[test]
+    fn test_source() {
+        let json_error = serde_json::from_str::<u32>("x").unwrap_err();
+
+        let error = err_pay("Failed to decode payload").with_source(json_error);
+
+        assert_eq!(Type::Payload, error.typ);
+        assert!(error.source().is_some());
+        assert!(err_pay("Failed to decode payload").source().is_none());
+    }
+}
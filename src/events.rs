@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Callbacks fired by a `KeyStore`. Register with `KeyStore::add_listener`.
+///
+/// All methods do nothing by default. They are called synchronously (while a `SharedKeyStore` is
+/// being updated, for loads) and should return quickly.
+pub trait KeyStoreListener: Send + Sync {
+    /// A key source was loaded
+    fn on_load(&self, _event: &LoadEvent) {}
+
+    /// A key source could not be loaded. The store keeps the keys it had.
+    fn on_load_failure(&self, _event: &LoadFailureEvent) {}
+
+    /// A token was rejected by `verify`, `verify_time`, `verify_raw` or `verify_raw_time`.
+    /// See `Error::typ` for the reason.
+    fn on_verify_failure(&self, _error: &Error) {}
+}
+
+/// Successful load of a key source
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadEvent {
+    pub url: String,
+    /// Key ids added to the store
+    pub added: Vec<String>,
+    /// Key ids removed from the store because no source publishes them anymore
+    pub removed: Vec<String>,
+    /// Number of keys the source publishes
+    pub keys: usize,
+    /// Time taken to download the key set
+    pub duration: Duration,
+}
+
+/// Failed load of a key source
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadFailureEvent {
+    pub url: String,
+    pub error: Error,
+    /// Time spent before the load failed
+    pub duration: Duration,
+}
@@ -0,0 +1,280 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::*;
+use crate::events::KeyStoreListener;
+use crate::jwt::Jwt;
+#[cfg(feature = "tracing")]
+use crate::keyset::verify_span;
+use crate::keyset::{decode_raw_token, notify_verify, KeyStore};
+#[cfg(feature = "metrics")]
+use crate::telemetry;
+
+/// Verification rules applied to the tokens of an issuer, in addition to those of its `KeyStore`
+#[derive(Debug, Clone, Default)]
+pub struct IssuerPolicy {
+    audiences: Vec<String>,
+    algorithms: Vec<String>,
+}
+
+impl IssuerPolicy {
+    pub fn new() -> IssuerPolicy {
+        IssuerPolicy::default()
+    }
+
+    /// Accept tokens whose `aud` contains `audience`. Any audience is accepted if none is added.
+    pub fn add_audience(&mut self, audience: &str) {
+        self.audiences.push(audience.to_string());
+    }
+
+    /// Accept tokens signed with `alg`. Any supported algorithm is accepted if none is added.
+    pub fn add_algorithm(&mut self, alg: &str) {
+        self.algorithms.push(alg.to_string());
+    }
+
+    pub fn audiences(&self) -> &[String] {
+        &self.audiences
+    }
+
+    pub fn algorithms(&self) -> &[String] {
+        &self.algorithms
+    }
+
+    fn check(&self, jwt: &Jwt) -> Result<(), Error> {
+        if !self.algorithms.is_empty() {
+            let alg = jwt.header().alg().unwrap_or("none");
+            if !self.algorithms.iter().any(|a| a == alg) {
+                return Err(err_hea("Algorithm not allowed for issuer").with_expected(self.algorithms.join(", "), alg));
+            }
+        }
+
+        if !self.audiences.is_empty() {
+            let audiences: Vec<&str> = match jwt.payload().get_array("aud") {
+                Some(audiences) => audiences.iter().filter_map(Value::as_str).collect(),
+                None => jwt.payload().aud().into_iter().collect(),
+            };
+
+            if !audiences.iter().any(|aud| self.audiences.iter().any(|a| a == aud)) {
+                return Err(err_pay("Audience not allowed for issuer").with_claim("aud").with_expected(self.audiences.join(", "), audiences.join(", ")));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Issuer {
+    key_store: KeyStore,
+    policy: IssuerPolicy,
+}
+
+#[derive(Deserialize)]
+struct IssuerClaim<'a> {
+    #[serde(borrow)]
+    iss: Option<Cow<'a, str>>,
+}
+
+/// Key stores of several issuers (identity providers).
+///
+/// Tokens are routed to the key store of their (unverified) `iss` claim. Once verified, the `iss`
+/// must still be the issuer the token was routed to and the token must satisfy the issuer's policy.
+#[derive(Default)]
+pub struct IssuerKeyStores {
+    issuers: HashMap<String, Issuer>,
+    listeners: Vec<Arc<dyn KeyStoreListener>>,
+}
+
+impl IssuerKeyStores {
+    pub fn new() -> IssuerKeyStores {
+        IssuerKeyStores::default()
+    }
+
+    /// Add (or replace) the key store of `iss`, with the default policy
+    pub fn add_issuer(&mut self, iss: &str, key_store: KeyStore) {
+        self.add_issuer_with_policy(iss, key_store, IssuerPolicy::default());
+    }
+
+    /// Add (or replace) the key store and policy of `iss`
+    pub fn add_issuer_with_policy(&mut self, iss: &str, key_store: KeyStore, policy: IssuerPolicy) {
+        self.issuers.insert(iss.to_string(), Issuer { key_store, policy });
+    }
+
+    /// Remove an issuer and return its key store
+    pub fn remove_issuer(&mut self, iss: &str) -> Option<KeyStore> {
+        self.issuers.remove(iss).map(|issuer| issuer.key_store)
+    }
+
+    pub fn key_store(&self, iss: &str) -> Option<&KeyStore> {
+        self.issuers.get(iss).map(|issuer| &issuer.key_store)
+    }
+
+    /// Key store of `iss`, for example to refresh its keys
+    pub fn key_store_mut(&mut self, iss: &str) -> Option<&mut KeyStore> {
+        self.issuers.get_mut(iss).map(|issuer| &mut issuer.key_store)
+    }
+
+    pub fn policy(&self, iss: &str) -> Option<&IssuerPolicy> {
+        self.issuers.get(iss).map(|issuer| &issuer.policy)
+    }
+
+    pub fn issuers(&self) -> impl Iterator<Item = &str> {
+        self.issuers.keys().map(String::as_str)
+    }
+
+    pub fn issuers_len(&self) -> usize {
+        self.issuers.len()
+    }
+
+    /// Notify `listener` of tokens rejected before they reach a key store (no or unknown `iss`).
+    /// The listeners of the issuers' key stores are notified of the other failures.
+    pub fn add_listener(&mut self, listener: Arc<dyn KeyStoreListener>) {
+        self.listeners.push(listener);
+    }
+
+    pub fn clear_listeners(&mut self) {
+        self.listeners.clear();
+    }
+
+    pub fn verify_time(&self, token: &str, time: SystemTime) -> Result<Jwt, Error> {
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+
+        let (iss, issuer) = match self.route(token) {
+            Ok(route) => route,
+            Err(error) => {
+                #[cfg(feature = "tracing")]
+                let _span = verify_span().entered();
+                #[cfg(feature = "metrics")]
+                telemetry::record_verification(Some(&error), Some(telemetry::UNKNOWN_ISSUER), start.elapsed());
+
+                return notify_verify(&self.listeners, Err(error));
+            }
+        };
+
+        issuer.key_store.verify_time_with(token, time, Some(&iss), |jwt| {
+            if jwt.payload().iss() != Some(iss.as_str()) {
+                return Err(err_pay("Issuer does not match").with_claim("iss").with_expected(iss.as_str(), jwt.payload().iss().unwrap_or("none")));
+            }
+
+            issuer.policy.check(jwt)
+        })
+    }
+
+    /// Configured issuer of the token, from its unverified `iss` claim
+    fn route(&self, token: &str) -> Result<(String, &Issuer), Error> {
+        let raw = decode_raw_token(token)?;
+        let claim = raw.payload::<IssuerClaim>()?;
+
+        let iss = claim.iss.ok_or_else(|| err_pay("Token does not have an issuer").with_claim("iss"))?;
+
+        let issuer = self.issuers.get(iss.as_ref()).ok_or_else(|| err_pay("Unknown issuer").with_claim("iss").with_expected("a configured issuer", iss.as_ref()))?;
+
+        Ok((iss.into_owned(), issuer))
+    }
+
+    /// Verify a token with the key store of its issuer.
+    ///
+    /// In addition to the checks of `KeyStore::verify`, the token must have an `iss` claim of a
+    /// configured issuer and satisfy the issuer's policy.
+    pub fn verify(&self, token: &str) -> Result<Jwt, Error> {
+        self.verify_time(token, SystemTime::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime};
+
+    use serde_json::json;
+
+    use crate::error::{Error, Type};
+    use crate::events::KeyStoreListener;
+    use crate::issuers::{IssuerKeyStores, IssuerPolicy};
+    use crate::jwt::Header;
+    use crate::private_keyset::PrivateKeySet;
+
+    #[derive(Default)]
+    struct FailureListener {
+        failures: Mutex<Vec<Type>>,
+    }
+
+    impl KeyStoreListener for FailureListener {
+        fn on_verify_failure(&self, error: &Error) {
+            self.failures.lock().unwrap().push(error.typ);
+        }
+    }
+
+    fn time() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::new(400, 0)
+    }
+
+    #[test]
+    fn test_route_by_issuer() {
+        let mut first = PrivateKeySet::new();
+        let first_kid = first.generate_ec("ES256").unwrap();
+
+        let mut second = PrivateKeySet::new();
+        let second_kid = second.generate_ed25519().unwrap();
+
+        let mut policy = IssuerPolicy::new();
+        policy.add_audience("api");
+        policy.add_algorithm("EdDSA");
+
+        let first_listener = Arc::new(FailureListener::default());
+        let mut first_key_store = first.key_store().unwrap();
+        first_key_store.add_listener(first_listener.clone());
+
+        let mut issuers = IssuerKeyStores::new();
+        issuers.add_issuer("https://first", first_key_store);
+        issuers.add_issuer_with_policy("https://second", second.key_store().unwrap(), policy);
+
+        assert_eq!(2, issuers.issuers_len());
+
+        let header = Header::new(json!({}));
+
+        let token = first.encode(&first_kid, &header, &json!({ "iss": "https://first", "exp": 500 })).unwrap();
+        assert_eq!(Some("https://first"), issuers.verify_time(&token, time()).unwrap().payload().iss());
+
+        let token = second.encode(&second_kid, &header, &json!({ "iss": "https://second", "aud": ["other", "api"], "exp": 500 })).unwrap();
+        assert!(issuers.verify_time(&token, time()).is_ok());
+
+        // Audience policy
+        let token = second.encode(&second_kid, &header, &json!({ "iss": "https://second", "aud": "other", "exp": 500 })).unwrap();
+        let error = issuers.verify_time(&token, time()).unwrap_err();
+        assert_eq!(Type::Payload, error.typ);
+        assert_eq!(Some("aud"), error.claim());
+
+        // Signed by the first issuer but claiming to be the second
+        let token = first.encode(&first_kid, &header, &json!({ "iss": "https://second", "aud": "api", "exp": 500 })).unwrap();
+        assert_eq!(Type::Key, issuers.verify_time(&token, time()).unwrap_err().typ);
+
+        let token = first.encode(&first_kid, &header, &json!({ "iss": "https://unknown" })).unwrap();
+        assert_eq!(Some("https://unknown"), issuers.verify_time(&token, time()).unwrap_err().actual());
+
+        let token = first.encode(&first_kid, &header, &json!({})).unwrap();
+        assert_eq!(Some("iss"), issuers.verify_time(&token, time()).unwrap_err().claim());
+
+        // Routing failures are notified to the listeners of `IssuerKeyStores`, the other failures to
+        // the listeners of the issuer's key store
+        let listener = Arc::new(FailureListener::default());
+        issuers.add_listener(listener.clone());
+
+        assert!(issuers.verify_time(&token, time()).is_err());
+        assert!(issuers.verify_time("a.b", time()).is_err());
+
+        let token = first.encode(&first_kid, &header, &json!({ "iss": "https://first", "exp": 300 })).unwrap();
+        assert_eq!(Type::Expired, issuers.verify_time(&token, time()).unwrap_err().typ);
+
+        assert_eq!(vec![Type::Payload, Type::Invalid], *listener.failures.lock().unwrap());
+        assert_eq!(vec![Type::Expired], *first_listener.failures.lock().unwrap());
+
+        assert!(issuers.remove_issuer("https://first").is_some());
+        assert!(issuers.key_store("https://first").is_none());
+    }
+}
@@ -1,10 +1,10 @@
-use std::ops::Add;
+use std::borrow::Cow;
 use std::time::{Duration, SystemTime};
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{Map, Value};
 
-use crate::error::{err_inv, Error};
+use crate::error::{err_hea, err_inv, err_pay, Error};
 
 macro_rules! impl_segment {
     () => (
@@ -47,12 +47,12 @@ macro_rules! impl_segment {
         }
 
         pub fn into<T: DeserializeOwned>(&self) -> Result<T, Error> {
-            Ok(serde_json::from_value::<T>(self.json.clone()).or(Err(err_inv("Failed to deserialize segment")))?)
+            serde_json::from_value::<T>(self.json.clone()).map_err(|e| err_inv("Failed to deserialize segment").with_source(e))
         }
     )
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Header {
     pub(crate) json: Value,
 }
@@ -88,14 +88,20 @@ impl Header {
         self.get_str("x5u")
     }
 
-    pub fn x5c(&self) -> Option<&str> {
-        self.get_str("x5c")
+    /// X.509 certificate chain (base64 DER, leaf first)
+    pub fn x5c(&self) -> Option<Vec<&str>> {
+        self.json.get("x5c")?.as_array()?.iter().map(Value::as_str).collect()
     }
 
     pub fn x5t(&self) -> Option<&str> {
         self.get_str("x5t")
     }
 
+    /// SHA-256 thumbprint of the X.509 certificate (`x5t#S256`)
+    pub fn x5t_s256(&self) -> Option<&str> {
+        self.get_str("x5t#S256")
+    }
+
     pub fn typ(&self) -> Option<&str> {
         self.get_str("typ")
     }
@@ -109,7 +115,7 @@ impl Header {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Payload {
     pub(crate) json: Value,
 }
@@ -130,47 +136,43 @@ impl Payload {
     }
 
     pub fn exp(&self) -> Option<u64> {
-        self.get_f64("exp").and_then(|f| Some(f as u64))
+        self.get_f64("exp").map(|f| f as u64)
     }
 
     pub fn nbf(&self) -> Option<u64> {
-        self.get_f64("nbf").and_then(|f| Some(f as u64))
+        self.get_f64("nbf").map(|f| f as u64)
     }
 
     pub fn iat(&self) -> Option<u64> {
-        self.get_f64("iat").and_then(|f| Some(f as u64))
+        self.get_f64("iat").map(|f| f as u64)
     }
 
     pub fn jti(&self) -> Option<&str> {
         self.get_str("jti")
     }
 
+    /// Time of `exp`. None if absent or too large to be represented as a `SystemTime`.
     pub fn expiry(&self) -> Option<SystemTime> {
-        if let Some(time) = self.exp() {
-            Some(SystemTime::UNIX_EPOCH.add(Duration::new(time, 0)))
-        } else {
-            None
-        }
+        self.exp().and_then(claim_time)
     }
 
+    /// Time of `iat`. None if absent or too large to be represented as a `SystemTime`.
     pub fn issued_at(&self) -> Option<SystemTime> {
-        if let Some(time) = self.iat() {
-            Some(SystemTime::UNIX_EPOCH.add(Duration::new(time, 0)))
-        } else {
-            None
-        }
+        self.iat().and_then(claim_time)
     }
 
+    /// Time of `nbf`. None if absent or too large to be represented as a `SystemTime`.
     pub fn not_before(&self) -> Option<SystemTime> {
-        if let Some(time) = self.nbf() {
-            Some(SystemTime::UNIX_EPOCH.add(Duration::new(time, 0)))
-        } else {
-            None
-        }
+        self.nbf().and_then(claim_time)
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Time of a claim in seconds since UNIX epoch, if `SystemTime` can represent it
+fn claim_time(seconds: u64) -> Option<SystemTime> {
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Jwt {
     header: Header,
     payload: Payload,
@@ -203,10 +205,7 @@ impl Jwt {
     }
 
     pub fn expired_time(&self, time: SystemTime) -> Option<bool> {
-        match self.payload.expiry() {
-            Some(token_time) => Some(time > token_time),
-            None => None,
-        }
+        self.payload.expiry().map(|token_time| time > token_time)
     }
 
     pub fn early(&self) -> Option<bool> {
@@ -214,17 +213,11 @@ impl Jwt {
     }
 
     pub fn early_time(&self, time: SystemTime) -> Option<bool> {
-        match self.payload.not_before() {
-            Some(token_time) => Some(time < token_time),
-            None => None,
-        }
+        self.payload.not_before().map(|token_time| time < token_time)
     }
 
     pub fn issued_by(&self, issuer: &str) -> Option<bool> {
-        match self.payload.iss() {
-            Some(t) => Some(t == issuer),
-            None => None,
-        }
+        self.payload.iss().map(|t| t == issuer)
     }
 
     pub fn valid(&self) -> Option<bool> {
@@ -236,11 +229,120 @@ impl Jwt {
     }
 }
 
+/// A JWT with a base64 decoded header and payload that have not been parsed.
+///
+/// The header and payload are deserialized directly into user types, without building a
+/// `serde_json::Value` first. Types can borrow strings from the decoded buffers (ie: `&str` or
+/// `Cow<str>` with `#[serde(borrow)]`).
+#[derive(Debug)]
+pub struct RawJwt {
+    header: Vec<u8>,
+    payload: Vec<u8>,
+    signature: String,
+}
+
+impl RawJwt {
+    pub fn new(header: Vec<u8>, payload: Vec<u8>, signature: String) -> Self {
+        RawJwt {
+            header,
+            payload,
+            signature,
+        }
+    }
+
+    /// Decoded JSON of the header
+    pub fn header_bytes(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// Decoded JSON of the payload
+    pub fn payload_bytes(&self) -> &[u8] {
+        &self.payload
+    }
+
+    pub fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    /// Deserialize the header into `T`
+    pub fn header<'a, T: Deserialize<'a>>(&'a self) -> Result<T, Error> {
+        serde_json::from_slice(&self.header).map_err(|e| err_hea("Failed to deserialize header").with_source(e))
+    }
+
+    /// Deserialize the payload into `T`
+    pub fn payload<'a, T: Deserialize<'a>>(&'a self) -> Result<T, Error> {
+        serde_json::from_slice(&self.payload).map_err(|e| err_pay("Failed to deserialize payload").with_source(e))
+    }
+}
+
+/// Header fields required to verify a token
+#[derive(Deserialize)]
+pub(crate) struct RegisteredHeader<'a> {
+    #[serde(borrow)]
+    pub(crate) alg: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub(crate) kid: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub(crate) x5t: Option<Cow<'a, str>>,
+    #[serde(borrow, rename = "x5t#S256")]
+    pub(crate) x5t_s256: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub(crate) x5c: Option<Vec<Cow<'a, str>>>,
+}
+
+impl<'a> RegisteredHeader<'a> {
+    pub(crate) fn from_header(header: &'a Header) -> RegisteredHeader<'a> {
+        RegisteredHeader {
+            alg: header.alg().map(Cow::Borrowed),
+            kid: header.kid().map(Cow::Borrowed),
+            x5t: header.x5t().map(Cow::Borrowed),
+            x5t_s256: header.x5t_s256().map(Cow::Borrowed),
+            x5c: header.x5c().map(|x5c| x5c.into_iter().map(Cow::Borrowed).collect()),
+        }
+    }
+}
+
+/// Payload claims required to verify a token
+#[derive(Deserialize)]
+pub(crate) struct RegisteredClaims {
+    exp: Option<f64>,
+    nbf: Option<f64>,
+}
+
+impl RegisteredClaims {
+    /// Claims of a parsed payload, deserialized like the payload of a `RawJwt`: an `exp` or `nbf`
+    /// that is not a number makes the payload invalid.
+    pub(crate) fn from_payload(payload: &Payload) -> Result<RegisteredClaims, Error> {
+        RegisteredClaims::deserialize(&payload.json).map_err(|e| err_pay("Failed to deserialize payload").with_source(e))
+    }
+
+    /// Time of `exp`. A time that `SystemTime` cannot represent makes the token invalid.
+    pub(crate) fn expiry(&self) -> Result<Option<SystemTime>, Error> {
+        self.exp.map(|time| claim_time(time as u64).ok_or_else(|| err_inv("Expiry time out of range").with_claim("exp"))).transpose()
+    }
+
+    /// Time of `nbf`. A time that `SystemTime` cannot represent makes the token invalid.
+    pub(crate) fn not_before(&self) -> Result<Option<SystemTime>, Error> {
+        self.nbf.map(|time| claim_time(time as u64).ok_or_else(|| err_inv("Not before time out of range").with_claim("nbf"))).transpose()
+    }
+
+    pub(crate) fn expired_time(&self, time: SystemTime) -> Result<bool, Error> {
+        Ok(matches!(self.expiry()?, Some(token_time) if time > token_time))
+    }
+
+    pub(crate) fn early_time(&self, time: SystemTime) -> Result<bool, Error> {
+        Ok(matches!(self.not_before()?, Some(token_time) if time < token_time))
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::time::{Duration, SystemTime};
+
     use serde_json::json;
 
-    use crate::jwt::{Header, Payload};
+    use crate::error::Type;
+    use crate::jwt::{Header, Payload, RegisteredClaims};
 
     #[test]
     fn test_header() {
@@ -252,7 +354,7 @@ mod tests {
             "jkw": "test_jkw",
             "kid": "test_kid",
             "x5u": "test_x5u",
-            "x5c": "test_x5c",
+            "x5c": ["test_x5c", "test_x5c_ca"],
             "x5t": "test_x5t",
             "typ": "test_typ",
             "cty": "test_cty",
@@ -268,7 +370,7 @@ mod tests {
         assert_eq!("test_jkw", test_header.jkw().unwrap());
         assert_eq!("test_kid", test_header.kid().unwrap());
         assert_eq!("test_x5u", test_header.x5u().unwrap());
-        assert_eq!("test_x5c", test_header.x5c().unwrap());
+        assert_eq!(vec!["test_x5c", "test_x5c_ca"], test_header.x5c().unwrap());
         assert_eq!("test_x5t", test_header.x5t().unwrap());
         assert_eq!("test_typ", test_header.typ().unwrap());
         assert_eq!("test_cty", test_header.cty().unwrap());
@@ -297,4 +399,28 @@ mod tests {
         assert_eq!(456u64, payload.nbf().unwrap());
         assert_eq!("test_jti", payload.jti().unwrap());
     }
+
+    #[test]
+    fn test_time_out_of_range() {
+        let payload = Payload::new(json!({ "exp": 1e20, "nbf": 1e20, "iat": 1e20 }));
+
+        assert_eq!(None, payload.expiry());
+        assert_eq!(None, payload.not_before());
+        assert_eq!(None, payload.issued_at());
+
+        let claims = RegisteredClaims::from_payload(&payload).unwrap();
+        let time = SystemTime::UNIX_EPOCH + Duration::new(1_000_000_000, 0);
+
+        let error = claims.expired_time(time).unwrap_err();
+        assert_eq!(Type::Invalid, error.typ);
+        assert_eq!(Some("exp"), error.claim());
+
+        let error = claims.early_time(time).unwrap_err();
+        assert_eq!(Type::Invalid, error.typ);
+        assert_eq!(Some("nbf"), error.claim());
+
+        let claims = RegisteredClaims::from_payload(&Payload::new(json!({ "exp": 1_000_000_001, "nbf": 999_999_999 }))).unwrap();
+        assert!(!claims.expired_time(time).unwrap());
+        assert!(!claims.early_time(time).unwrap());
+    }
 }
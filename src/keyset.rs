@@ -1,42 +1,209 @@
-use std::time::{Duration, SystemTime};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use base64::{decode_config, URL_SAFE_NO_PAD};
+use base64::{decode_config, encode_config, STANDARD, URL_SAFE_NO_PAD};
 use regex::Regex;
 use reqwest;
-use reqwest::Response;
-use ring::signature::{RsaPublicKeyComponents, RSA_PKCS1_2048_8192_SHA256};
+use reqwest::{Client, Response};
+use ring::digest::{digest, SHA256};
+use ring::signature::{
+    RsaParameters, RsaPublicKeyComponents, UnparsedPublicKey, ECDSA_P256_SHA256_FIXED, ECDSA_P384_SHA384_FIXED, ED25519,
+    RSA_PKCS1_2048_8192_SHA256, RSA_PKCS1_2048_8192_SHA384, RSA_PKCS1_2048_8192_SHA512, RSA_PSS_2048_8192_SHA256,
+    RSA_PSS_2048_8192_SHA384, RSA_PSS_2048_8192_SHA512,
+};
 use serde::{
     de::DeserializeOwned,
     {Deserialize, Serialize},
 };
 use serde_json::Value;
 
+use crate::cache::{token_hash, LockedCache};
 use crate::error::*;
+use crate::events::{KeyStoreListener, LoadEvent, LoadFailureEvent};
 use crate::jwt::*;
+use crate::pem::decode_pem;
+#[cfg(feature = "metrics")]
+use crate::telemetry;
+use crate::x509;
 
 type HeaderBody = String;
 pub type Signature = String;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JwtKey {
-    #[serde(default)] // https://github.com/jfbilodeau/jwks-client/issues/1
+    #[serde(default, skip_serializing_if = "String::is_empty")] // https://github.com/jfbilodeau/jwks-client/issues/1
     pub e: String,
     pub kty: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub alg: Option<String>,
-    #[serde(default)] // https://github.com/jfbilodeau/jwks-client/issues/1
+    #[serde(default, skip_serializing_if = "String::is_empty")] // https://github.com/jfbilodeau/jwks-client/issues/1
     pub n: String,
+    /// Curve of EC (`P-256`, `P-384`) and OKP (`Ed25519`) keys
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub crv: String,
+    /// X coordinate of EC keys, or public key of OKP keys
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub x: String,
+    /// Y coordinate of EC keys
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub y: String,
+    /// Key id. Empty if the JWKS entry does not have one
+    #[serde(default)]
     pub kid: String,
+    /// Intended use of the key (`sig` or `enc`)
+    #[serde(rename = "use", default, skip_serializing_if = "Option::is_none")]
+    pub key_use: Option<String>,
+    /// Operations the key may be used for (ie: `verify`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_ops: Option<Vec<String>>,
+    /// SHA-1 thumbprint of the key's X.509 certificate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x5t: Option<String>,
+    /// SHA-256 thumbprint of the key's X.509 certificate
+    #[serde(rename = "x5t#S256", default, skip_serializing_if = "Option::is_none")]
+    pub x5t_s256: Option<String>,
+    /// X.509 certificate chain (base64 DER, leaf first). The leaf certificate holds the public key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x5c: Option<Vec<String>>,
 }
 
 impl JwtKey {
+    /// RSA key from base64url `n` and `e`, without validation. See `new_rsa`.
     pub fn new(kid: &str, n: &str, e: &str) -> JwtKey {
         JwtKey {
             e: e.to_owned(),
-            kty: "JTW".to_string(),
-            alg: Some("RS256".to_string()),
+            kty: "RSA".to_string(),
+            alg: None,
             n: n.to_owned(),
+            crv: "".to_owned(),
+            x: "".to_owned(),
+            y: "".to_owned(),
             kid: kid.to_owned(),
+            key_use: None,
+            key_ops: None,
+            x5t: None,
+            x5t_s256: None,
+            x5c: None,
+        }
+    }
+
+    /// RSA key from base64url `n` and `e`.
+    ///
+    /// The modulus must be an odd number of 2048 to 8192 bits and the exponent an odd number from 3
+    /// to 2^33 - 1.
+    pub fn new_rsa(kid: &str, n: &str, e: &str) -> Result<JwtKey, Error> {
+        let key = JwtKey::new(kid, n, e);
+
+        PublicKey::from_rsa_key(&key)?;
+
+        Ok(key)
+    }
+
+    /// EC key from `crv` (`P-256` or `P-384`) and base64url `x` and `y`.
+    ///
+    /// The point must be on the curve.
+    pub fn new_ec(kid: &str, crv: &str, x: &str, y: &str) -> Result<JwtKey, Error> {
+        let mut key = JwtKey::new(kid, "", "");
+        key.kty = "EC".to_string();
+        key.crv = crv.to_owned();
+        key.x = x.to_owned();
+        key.y = y.to_owned();
+
+        PublicKey::from_ec_key(&key)?;
+
+        Ok(key)
+    }
+
+    /// Ed25519 key (`OKP`) from base64url `x`
+    pub fn new_ed25519(kid: &str, x: &str) -> Result<JwtKey, Error> {
+        let mut key = JwtKey::new(kid, "", "");
+        key.kty = "OKP".to_string();
+        key.crv = "Ed25519".to_string();
+        key.x = x.to_owned();
+
+        PublicKey::from_okp_key(&key)?;
+
+        Ok(key)
+    }
+
+    /// Load a public key from PEM.
+    ///
+    /// Accepts `PUBLIC KEY` (SPKI), `RSA PUBLIC KEY` (PKCS#1) and `CERTIFICATE` (X.509) with RSA,
+    /// EC (P-256, P-384) or Ed25519 keys. Without `kid`, the RFC 7638 thumbprint is used.
+    pub fn from_pem(kid: Option<&str>, pem: &str) -> Result<JwtKey, Error> {
+        let (label, der) = decode_pem(pem)?;
+
+        match label.as_str() {
+            "PUBLIC KEY" => JwtKey::from_spki_der(kid, &der),
+            "RSA PUBLIC KEY" => JwtKey::from_rsa_der(kid, &der),
+            "CERTIFICATE" => JwtKey::from_certificate_der(kid, &der),
+            _ => Err(err_key("Unsupported PEM type").with_expected("PUBLIC KEY, RSA PUBLIC KEY or CERTIFICATE", label)),
+        }
+    }
+
+    /// Load a public key from SPKI DER (`SubjectPublicKeyInfo`)
+    pub fn from_spki_der(kid: Option<&str>, der: &[u8]) -> Result<JwtKey, Error> {
+        x509::spki_public_key(der)?.to_jwt_key(kid)
+    }
+
+    /// Load an RSA public key from PKCS#1 DER (`RSAPublicKey`)
+    pub fn from_rsa_der(kid: Option<&str>, der: &[u8]) -> Result<JwtKey, Error> {
+        x509::rsa_public_key(der)?.to_jwt_key(kid)
+    }
+
+    /// Load the public key of an X.509 DER certificate. The certificate is kept in `x5c`.
+    pub fn from_certificate_der(kid: Option<&str>, der: &[u8]) -> Result<JwtKey, Error> {
+        let mut key = x509::certificate_public_key(der)?.to_jwt_key(kid)?;
+
+        key.x5c = Some(vec![encode_config(der, STANDARD)]);
+
+        Ok(key)
+    }
+
+    /// Check that the key may verify a token signed with `alg`.
+    ///
+    /// The key is refused if its `use` is not `sig`, its `key_ops` does not contain `verify` or its
+    /// `alg` is not `alg`. Members that are absent do not restrict the key.
+    pub fn check_verify(&self, alg: &str) -> Result<(), Error> {
+        if let Some(key_use) = &self.key_use {
+            if key_use != "sig" {
+                return Err(err_key("Key is not a signature key").with_expected("sig", key_use.as_str()));
+            }
         }
+
+        if let Some(key_ops) = &self.key_ops {
+            if !key_ops.iter().any(|op| op == "verify") {
+                return Err(err_key("Key operations do not allow verification").with_expected("verify", key_ops.join(", ")));
+            }
+        }
+
+        if let Some(key_alg) = &self.alg {
+            if key_alg != alg {
+                return Err(err_key("Key algorithm does not match token algorithm").with_expected(key_alg.as_str(), alg));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// RFC 7638 JWK thumbprint (base64url encoded SHA-256)
+    ///
+    /// Supported for `RSA`, `EC` and `OKP` keys.
+    pub fn thumbprint(&self) -> Result<String, Error> {
+        fn json(value: &str) -> String {
+            Value::from(value).to_string()
+        }
+
+        // Required members only, in lexicographic order and without whitespace
+        let members = match self.kty.as_str() {
+            "RSA" => format!(r#"{{"e":{},"kty":"RSA","n":{}}}"#, json(&self.e), json(&self.n)),
+            "EC" => format!(r#"{{"crv":{},"kty":"EC","x":{},"y":{}}}"#, json(&self.crv), json(&self.x), json(&self.y)),
+            "OKP" => format!(r#"{{"crv":{},"kty":"OKP","x":{}}}"#, json(&self.crv), json(&self.x)),
+            _ => return Err(err_key("Unsupported key type").with_expected("RSA, EC or OKP", self.kty.as_str())),
+        };
+
+        Ok(encode_config(&digest(&SHA256, members.as_bytes()), URL_SAFE_NO_PAD))
     }
 }
 
@@ -47,38 +214,456 @@ impl Clone for JwtKey {
             kty: self.kty.clone(),
             alg: self.alg.clone(),
             n: self.n.clone(),
+            crv: self.crv.clone(),
+            x: self.x.clone(),
+            y: self.y.clone(),
             kid: self.kid.clone(),
+            key_use: self.key_use.clone(),
+            key_ops: self.key_ops.clone(),
+            x5t: self.x5t.clone(),
+            x5t_s256: self.x5t_s256.clone(),
+            x5c: self.x5c.clone(),
         }
     }
 }
 
-pub struct KeyStore {
-    key_url: String,
-    keys: Vec<JwtKey>,
-    refresh_interval: f64,
+/// JSON Web Key Set document (`{"keys": [...]}`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtKeys {
+    pub keys: Vec<JwtKey>,
+}
+
+impl JwtKeys {
+    /// Parse a JWKS document
+    pub fn from_json(json: &str) -> Result<JwtKeys, Error> {
+        serde_json::from_str(json).map_err(|e| err_jwk("Failed to parse keys").with_source(e))
+    }
+
+    /// Fetch a key by key id (KID)
+    pub fn key_by_id(&self, kid: &str) -> Option<&JwtKey> {
+        self.keys.iter().find(|k| k.kid == kid)
+    }
+}
+
+/// Outcome of the last key set load
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadReport {
+    /// Key ids of the keys that were added to the store
+    pub loaded: Vec<String>,
+    /// Keys that could not be parsed or are not supported
+    pub skipped: Vec<SkippedKey>,
+}
+
+/// JWKS entry that was not added to the store
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkippedKey {
+    /// Key id of the entry, if it has one
+    pub kid: Option<String>,
+    pub reason: Error,
+}
+
+/// JWKS document whose keys are parsed one at a time
+#[derive(Serialize, Deserialize)]
+struct RawJwtKeys {
+    keys: Vec<Value>,
+}
+
+/// Saved key set: the JWKS document wrapped with its load metadata.
+///
+/// Times are in seconds since UNIX epoch.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    url: String,
+    load_time: Option<u64>,
+    expire_time: Option<u64>,
+    refresh_time: Option<u64>,
+    /// Key sources. Absent if the store has at most one source
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sources: Vec<SourceSnapshot>,
+    jwks: RawJwtKeys,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SourceSnapshot {
+    url: String,
+    load_time: Option<u64>,
+    expire_time: Option<u64>,
+    refresh_time: Option<u64>,
+    kids: Vec<String>,
+}
+
+/// Public key material decoded from a `JwtKey`, ready for signature verification
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PublicKey {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    /// Uncompressed point (`0x04 || x || y`)
+    Ec { crv: &'static str, point: Vec<u8> },
+    Ed25519 { x: Vec<u8> },
+}
+
+impl PublicKey {
+    /// Public key of a JWK.
+    ///
+    /// With `x5c`, the key is taken from the leaf certificate and must match `kty`, the JWK members
+    /// (`n`/`e`, `x`/`y`) and thumbprints (`x5t`, `x5t#S256`) when they are present.
+    fn from_jwt_key(key: &JwtKey) -> Result<PublicKey, Error> {
+        if !matches!(key.kty.as_str(), "RSA" | "EC" | "OKP") {
+            return Err(err_key("Unsupported key type").with_kid(&key.kid).with_expected("RSA, EC or OKP", key.kty.as_str()));
+        }
+
+        let certificate_key = match &key.x5c {
+            Some(x5c) => {
+                let chain = x509::decode_chain(x5c).map_err(|e| e.with_kid(&key.kid))?;
+                x509::check_thumbprints(&chain[0], key.x5t.as_deref(), key.x5t_s256.as_deref()).map_err(|e| e.with_kid(&key.kid))?;
+                Some(x509::certificate_public_key(&chain[0]).map_err(|e| e.with_kid(&key.kid))?)
+            }
+            None => None,
+        };
+
+        if let Some(certificate_key) = &certificate_key {
+            if certificate_key.kty() != key.kty {
+                return Err(err_cer("Key type does not match its certificate").with_kid(&key.kid).with_expected(key.kty.as_str(), certificate_key.kty()));
+            }
+
+            if key.n.is_empty() && key.x.is_empty() {
+                return Ok(certificate_key.clone());
+            }
+        }
+
+        let public_key = match key.kty.as_str() {
+            "EC" => PublicKey::from_ec_key(key)?,
+            "OKP" => PublicKey::from_okp_key(key)?,
+            _ => PublicKey::from_rsa_key(key)?,
+        };
+
+        if matches!(&certificate_key, Some(certificate_key) if *certificate_key != public_key) {
+            return Err(err_cer("Key does not match its certificate").with_kid(&key.kid));
+        }
+
+        Ok(public_key)
+    }
+
+    /// JWK `kty` of the key
+    fn kty(&self) -> &'static str {
+        match self {
+            PublicKey::Rsa { .. } => "RSA",
+            PublicKey::Ec { .. } => "EC",
+            PublicKey::Ed25519 { .. } => "OKP",
+        }
+    }
+
+    fn from_rsa_key(key: &JwtKey) -> Result<PublicKey, Error> {
+        let e = decode_config(&key.e, URL_SAFE_NO_PAD).map_err(|e| err_cer("Failed to decode exponent").with_kid(&key.kid).with_source(e))?;
+        let n = decode_config(&key.n, URL_SAFE_NO_PAD).map_err(|e| err_cer("Failed to decode modulus").with_kid(&key.kid).with_source(e))?;
+
+        if e.is_empty() {
+            return Err(err_cer("Missing exponent").with_kid(&key.kid));
+        }
+        if n.is_empty() {
+            return Err(err_cer("Missing modulus").with_kid(&key.kid));
+        }
+
+        let bits = bit_length(&n);
+        if !(RSA_MIN_BITS..=RSA_MAX_BITS).contains(&bits) {
+            return Err(err_cer("Unsupported RSA modulus size").with_kid(&key.kid).with_expected(format!("{} to {} bits", RSA_MIN_BITS, RSA_MAX_BITS), format!("{} bits", bits)));
+        }
+        // The product of two odd primes
+        if n[n.len() - 1] % 2 == 0 {
+            return Err(err_cer("Invalid RSA modulus").with_kid(&key.kid));
+        }
+
+        let exponent = if bit_length(&e) <= 33 { e.iter().fold(0u64, |value, byte| (value << 8) | *byte as u64) } else { 0 };
+        if exponent < 3 || exponent % 2 == 0 {
+            return Err(err_cer("Invalid RSA exponent").with_kid(&key.kid));
+        }
+
+        Ok(PublicKey::Rsa { n, e })
+    }
+
+    fn from_ec_key(key: &JwtKey) -> Result<PublicKey, Error> {
+        let (crv, size) = match key.crv.as_str() {
+            "P-256" => ("P-256", 32),
+            "P-384" => ("P-384", 48),
+            _ => return Err(err_key("Unsupported curve").with_kid(&key.kid).with_expected("P-256 or P-384", key.crv.as_str())),
+        };
+
+        let x = decode_config(&key.x, URL_SAFE_NO_PAD).map_err(|e| err_cer("Failed to decode x coordinate").with_kid(&key.kid).with_source(e))?;
+        let y = decode_config(&key.y, URL_SAFE_NO_PAD).map_err(|e| err_cer("Failed to decode y coordinate").with_kid(&key.kid).with_source(e))?;
+
+        if x.len() != size || y.len() != size {
+            return Err(err_cer("Invalid coordinate length").with_kid(&key.kid));
+        }
+
+        let mut point = Vec::with_capacity(1 + 2 * size);
+        point.push(4);
+        point.extend_from_slice(&x);
+        point.extend_from_slice(&y);
+
+        PublicKey::from_ec_point(crv, &point).map_err(|e| e.with_kid(&key.kid))
+    }
+
+    /// EC key from an uncompressed point (`0x04 || x || y`), which must be on the curve `crv`
+    pub(crate) fn from_ec_point(crv: &'static str, point: &[u8]) -> Result<PublicKey, Error> {
+        let on_curve = match crv {
+            "P-256" => p256::PublicKey::from_sec1_bytes(point).is_ok(),
+            "P-384" => p384::PublicKey::from_sec1_bytes(point).is_ok(),
+            _ => false,
+        };
+
+        if !on_curve {
+            return Err(err_cer("EC point is not on the curve"));
+        }
+
+        Ok(PublicKey::Ec { crv, point: point.to_vec() })
+    }
+
+    fn from_okp_key(key: &JwtKey) -> Result<PublicKey, Error> {
+        if key.crv != "Ed25519" {
+            return Err(err_key("Unsupported curve").with_kid(&key.kid).with_expected("Ed25519", key.crv.as_str()));
+        }
+
+        let x = decode_config(&key.x, URL_SAFE_NO_PAD).map_err(|e| err_cer("Failed to decode public key").with_kid(&key.kid).with_source(e))?;
+
+        if x.len() != 32 {
+            return Err(err_cer("Invalid public key length").with_kid(&key.kid));
+        }
+
+        Ok(PublicKey::Ed25519 { x })
+    }
+
+    /// JWK of the key. Without `kid`, the RFC 7638 thumbprint is used.
+    fn to_jwt_key(&self, kid: Option<&str>) -> Result<JwtKey, Error> {
+        let mut key = JwtKey::new(kid.unwrap_or(""), "", "");
+
+        match self {
+            PublicKey::Rsa { n, e } => {
+                key.kty = "RSA".to_string();
+                key.n = encode_config(n, URL_SAFE_NO_PAD);
+                key.e = encode_config(e, URL_SAFE_NO_PAD);
+            }
+            PublicKey::Ec { crv, point } => {
+                let size = (point.len() - 1) / 2;
+                key.kty = "EC".to_string();
+                key.crv = crv.to_string();
+                key.x = encode_config(&point[1..1 + size], URL_SAFE_NO_PAD);
+                key.y = encode_config(&point[1 + size..], URL_SAFE_NO_PAD);
+            }
+            PublicKey::Ed25519 { x } => {
+                key.kty = "OKP".to_string();
+                key.crv = "Ed25519".to_string();
+                key.x = encode_config(x, URL_SAFE_NO_PAD);
+            }
+        }
+
+        if kid.is_none() {
+            key.kid = key.thumbprint()?;
+        }
+
+        Ok(key)
+    }
+
+    /// True if the key can verify signatures made with `alg`
+    fn supports(&self, alg: &str) -> bool {
+        match self {
+            PublicKey::Rsa { .. } => rsa_parameters(alg).is_some(),
+            PublicKey::Ec { crv, .. } => matches!((*crv, alg), ("P-256", "ES256") | ("P-384", "ES384")),
+            PublicKey::Ed25519 { .. } => alg == "EdDSA",
+        }
+    }
+
+    /// Verify `signature` of `message` with algorithm `alg`
+    pub(crate) fn verify(&self, alg: &str, message: &str, signature: &str) -> Result<(), Error> {
+        let signature = decode_config(signature, URL_SAFE_NO_PAD).map_err(|e| err_sig("Could not base64 decode signature").with_source(e))?;
+
+        let result = match (self, alg) {
+            (PublicKey::Rsa { n, e }, _) => {
+                let parameters = rsa_parameters(alg).ok_or_else(|| err_key("Key type does not match algorithm").with_expected("RSA", alg))?;
+                RsaPublicKeyComponents { n, e }.verify(parameters, message.as_bytes(), &signature)
+            }
+            (PublicKey::Ec { crv: "P-256", point }, "ES256") => UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point).verify(message.as_bytes(), &signature),
+            (PublicKey::Ec { crv: "P-384", point }, "ES384") => UnparsedPublicKey::new(&ECDSA_P384_SHA384_FIXED, point).verify(message.as_bytes(), &signature),
+            (PublicKey::Ed25519 { x }, "EdDSA") => UnparsedPublicKey::new(&ED25519, x).verify(message.as_bytes(), &signature),
+            _ => return Err(err_key("Key type does not match algorithm").with_alg(alg)),
+        };
+
+        result.or(Err(err_cer("Signature does not match certificate")))
+    }
+}
+
+/// Number of significant bits of a big-endian unsigned integer
+fn bit_length(bytes: &[u8]) -> usize {
+    match bytes.iter().position(|b| *b != 0) {
+        Some(start) => (bytes.len() - start) * 8 - bytes[start].leading_zeros() as usize,
+        None => 0,
+    }
+}
+
+/// RSA modulus sizes accepted by ring
+const RSA_MIN_BITS: usize = 2048;
+const RSA_MAX_BITS: usize = 8192;
+
+/// Algorithms supported for verification: RSA PKCS#1 v1.5 and PSS with SHA-256/384/512, ECDSA on
+/// P-256 and P-384, and Ed25519. Tokens signed with any other algorithm (ie: `none`, `HS256`) are
+/// rejected.
+const ALGORITHMS: [&str; 9] = ["RS256", "RS384", "RS512", "PS256", "PS384", "PS512", "ES256", "ES384", "EdDSA"];
+
+fn rsa_parameters(alg: &str) -> Option<&'static RsaParameters> {
+    match alg {
+        "RS256" => Some(&RSA_PKCS1_2048_8192_SHA256),
+        "RS384" => Some(&RSA_PKCS1_2048_8192_SHA384),
+        "RS512" => Some(&RSA_PKCS1_2048_8192_SHA512),
+        "PS256" => Some(&RSA_PSS_2048_8192_SHA256),
+        "PS384" => Some(&RSA_PSS_2048_8192_SHA384),
+        "PS512" => Some(&RSA_PSS_2048_8192_SHA512),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StoredKey {
+    jwk: JwtKey,
+    public_key: PublicKey,
+    thumbprint: Option<String>,
+}
+
+/// JWKS endpoint of a `KeyStore`, with the cache state of the keys it published
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeySource {
+    url: String,
     load_time: Option<SystemTime>,
     expire_time: Option<SystemTime>,
     refresh_time: Option<SystemTime>,
+    /// Key ids published at the last load
+    kids: Vec<String>,
+    /// Consecutive failed loads
+    failures: u32,
+    /// Time before which a failed source is not loaded again by `refresh_key_sources`
+    retry_time: Option<SystemTime>,
+    last_error: Option<Error>,
 }
 
-impl KeyStore {
-    pub fn new() -> KeyStore {
-        let key_store = KeyStore {
-            key_url: "".to_owned(),
-            keys: vec![],
-            refresh_interval: 0.5,
+impl KeySource {
+    fn new(url: &str) -> KeySource {
+        KeySource {
+            url: url.to_string(),
             load_time: None,
             expire_time: None,
             refresh_time: None,
-        };
+            kids: vec![],
+            failures: 0,
+            retry_time: None,
+            last_error: None,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Time at which the keys of this source were last loaded
+    pub fn load_time(&self) -> Option<SystemTime> {
+        self.load_time
+    }
+
+    /// Time at which the keys of this source are considered expired
+    pub fn expire_time(&self) -> Option<SystemTime> {
+        self.expire_time
+    }
+
+    /// Time at which the keys of this source should be refreshed
+    pub fn refresh_time(&self) -> Option<SystemTime> {
+        self.refresh_time
+    }
+
+    /// Key ids published by this source at its last load
+    pub fn kids(&self) -> &[String] {
+        &self.kids
+    }
+
+    /// Number of consecutive failed loads
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Time after which `refresh_key_sources` tries again to load a failed source
+    pub fn retry_time(&self) -> Option<SystemTime> {
+        self.retry_time
+    }
+
+    /// Error of the last load, if it failed
+    pub fn last_error(&self) -> Option<&Error> {
+        self.last_error.as_ref()
+    }
 
-        key_store
+    /// True if the keys expired more than `max_staleness` before `time`
+    fn stale_time(&self, max_staleness: Duration, time: SystemTime) -> bool {
+        matches!(self.expire_time.and_then(|expire| expire.checked_add(max_staleness)), Some(limit) if limit <= time)
+    }
+}
+
+/// Keys used to verify tokens.
+///
+/// A clone has its own keys and settings. It shares the verification cache until either store
+/// changes its keys. See `shared::SharedKeyStore`
+/// to share a store between threads and refresh it without blocking verification.
+#[derive(Clone)]
+pub struct KeyStore {
+    /// JWKS endpoints. The first one is the key set URL
+    sources: Vec<KeySource>,
+    keys: HashMap<String, StoredKey>,
+    /// Thumbprint to `kid`
+    thumbprints: HashMap<String, String>,
+    thumbprint_kid: bool,
+    refresh_interval: f64,
+    verify_cache: Option<LockedCache>,
+    load_timeout: Option<Duration>,
+    load_report: LoadReport,
+    /// How long keys remain usable after they expire
+    max_staleness: Duration,
+    refuse_stale_keys: bool,
+    /// Delays before retrying a failed source: initial and maximum
+    retry_backoff: (Duration, Duration),
+    /// Maximum number of keys tried for tokens without `kid`
+    kidless_max_keys: Option<usize>,
+    /// DER certificates that `x5c` chains must lead to
+    trust_anchors: Vec<Vec<u8>>,
+    listeners: Vec<Arc<dyn KeyStoreListener>>,
+    /// Client of the key set downloads, created by the first download and shared with the clones of
+    /// the store made after it
+    client: OnceLock<Client>,
+}
+
+impl Default for KeyStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyStore {
+    pub fn new() -> KeyStore {
+        KeyStore {
+            sources: vec![],
+            keys: HashMap::new(),
+            thumbprints: HashMap::new(),
+            thumbprint_kid: false,
+            refresh_interval: 0.5,
+            verify_cache: None,
+            load_timeout: None,
+            load_report: LoadReport::default(),
+            max_staleness: Duration::from_secs(0),
+            refuse_stale_keys: false,
+            retry_backoff: (Duration::from_secs(1), Duration::from_secs(300)),
+            kidless_max_keys: None,
+            trust_anchors: vec![],
+            listeners: vec![],
+            client: OnceLock::new(),
+        }
     }
 
     pub async fn new_from(jkws_url: String) -> Result<KeyStore, Error> {
         let mut key_store = KeyStore::new();
 
-        key_store.key_url = jkws_url;
+        key_store.sources.push(KeySource::new(&jkws_url));
 
         key_store.load_keys().await?;
 
@@ -86,70 +671,449 @@ impl KeyStore {
     }
 
     pub fn clear_keys(&mut self) {
+        for source in self.sources.iter_mut() {
+            source.kids.clear();
+        }
         self.keys.clear();
+        self.thumbprints.clear();
+        self.clear_verify_cache();
     }
 
+    /// URL of the first key source, empty if there is none
     pub fn key_set_url(&self) -> &str {
-        &self.key_url
+        self.sources.first().map_or("", |source| source.url.as_str())
     }
 
+    /// Set the URL of the first key source and load the keys of all sources
     pub async fn load_keys_from(&mut self, url: String) -> Result<(), Error> {
-        self.key_url = url;
+        match self.sources.first_mut() {
+            Some(source) => source.url = url,
+            None => self.sources.push(KeySource::new(&url)),
+        }
 
         self.load_keys().await?;
 
         Ok(())
     }
 
+    /// Add a JWKS endpoint. Its keys are loaded by the next `load_keys` or `refresh_key_sources`.
+    ///
+    /// Keys of all sources are merged. A source cannot replace a key published by another source
+    /// under the same `kid` with different key material; such keys are skipped and listed in the
+    /// load report.
+    pub fn add_key_source(&mut self, url: &str) {
+        if !self.sources.iter().any(|source| source.url == url) {
+            self.sources.push(KeySource::new(url));
+        }
+    }
+
+    /// Remove a JWKS endpoint and the keys that only it published
+    pub fn remove_key_source(&mut self, url: &str) {
+        if let Some(index) = self.sources.iter().position(|source| source.url == url) {
+            let source = self.sources.remove(index);
+            self.remove_unpublished_keys(source.kids);
+
+            #[cfg(feature = "metrics")]
+            telemetry::record_removed_source(url);
+        }
+    }
+
+    /// JWKS endpoints, in the order they were added
+    pub fn key_sources(&self) -> &[KeySource] {
+        &self.sources
+    }
+
+    /// Download the key sets of all sources and add their keys to the store.
+    ///
+    /// Keys that cannot be used for verification (unsupported type or curve, malformed key material)
+    /// are skipped. Keys that a source no longer publishes are removed. If a source cannot be loaded,
+    /// the other sources are still loaded and the first error is returned.
     pub async fn load_keys(&mut self) -> Result<(), Error> {
-        #[derive(Deserialize)]
-        pub struct JwtKeys {
-            pub keys: Vec<JwtKey>,
+        if self.sources.is_empty() {
+            return Err(err_con("No JWKS URL"));
         }
 
-        let mut response = reqwest::get(&self.key_url).await.map_err(|_| err_con("Could not download JWKS"))?;
+        self.load_sources(self.source_urls()).await
+    }
+
+    /// Load the sources that were never loaded, whose refresh time has passed or whose last load
+    /// failed.
+    ///
+    /// A failed source keeps its keys and is retried after an exponential backoff (see
+    /// `set_retry_backoff`). Call this regularly, for example before verifying tokens.
+    pub async fn refresh_key_sources(&mut self) -> Result<(), Error> {
+        let due = self.due_sources(SystemTime::now());
+
+        self.load_sources(due).await
+    }
 
-        let load_time = SystemTime::now();
-        self.load_time = Some(load_time);
+    /// URLs of the sources that `refresh_key_sources` loads at `time`
+    pub(crate) fn due_sources(&self, time: SystemTime) -> Vec<String> {
+        self.sources
+            .iter()
+            .filter(|source| {
+                let due = source.load_time.is_none() || matches!(source.refresh_time, Some(refresh) if refresh <= time) || source.failures > 0;
+                due && !matches!(source.retry_time, Some(retry) if retry > time)
+            })
+            .map(|source| source.url.clone())
+            .collect()
+    }
 
-        let result = KeyStore::cache_max_age(&mut response);
+    #[cfg(feature = "metrics")]
+    pub(crate) fn record_metrics(&self) {
+        telemetry::record_sources(&self.sources);
+    }
+
+    /// URLs of all sources
+    pub(crate) fn source_urls(&self) -> Vec<String> {
+        self.sources.iter().map(|source| source.url.clone()).collect()
+    }
+
+    /// Client of the key set downloads
+    pub(crate) fn http_client(&self) -> Result<Client, Error> {
+        if let Some(client) = self.client.get() {
+            return Ok(client.clone());
+        }
+
+        let client = Client::builder().build().map_err(|e| err_int("Could not create HTTP client").with_source(e))?;
+
+        Ok(self.client.get_or_init(|| client).clone())
+    }
+
+    async fn load_sources(&mut self, urls: Vec<String>) -> Result<(), Error> {
+        let client = self.http_client()?;
+        let mut results = Vec::with_capacity(urls.len());
+
+        for url in urls {
+            results.push(fetch_source(url, &client, self.load_timeout).await);
+        }
+
+        self.add_key_sets(results)
+    }
+
+    /// Add downloaded key sets to their sources and record failures.
+    ///
+    /// Sources that were removed in the meantime are ignored. Returns the first error.
+    pub(crate) fn add_key_sets(&mut self, results: Vec<SourceLoad>) -> Result<(), Error> {
+        let mut report = LoadReport::default();
+        let mut result = Ok(());
+
+        for SourceLoad { url, duration, result: fetched } in results {
+            let index = match self.sources.iter().position(|source| source.url == url) {
+                Some(index) => index,
+                None => continue,
+            };
+
+            match fetched {
+                Ok(fetched) => {
+                    let previous: HashSet<String> = self.keys.keys().cloned().collect();
+
+                    let source_report = self.add_fetched_key_set(index, fetched);
+
+                    if !self.listeners.is_empty() {
+                        let mut added: Vec<String> = self.keys.keys().filter(|kid| !previous.contains(*kid)).cloned().collect();
+                        let mut removed: Vec<String> = previous.into_iter().filter(|kid| !self.keys.contains_key(kid)).collect();
+                        added.sort();
+                        removed.sort();
+
+                        let event = LoadEvent { url, added, removed, keys: source_report.loaded.len(), duration };
+                        self.listeners.iter().for_each(|listener| listener.on_load(&event));
+                    }
+
+                    let source = &mut self.sources[index];
+                    source.failures = 0;
+                    source.retry_time = None;
+                    source.last_error = None;
+
+                    report.loaded.extend(source_report.loaded);
+                    report.skipped.extend(source_report.skipped);
+                }
+                Err(e) => {
+                    let (initial, max) = self.retry_backoff;
+                    let source = &mut self.sources[index];
+                    source.failures = source.failures.saturating_add(1);
+                    let delay = initial.checked_mul(1 << (source.failures - 1).min(20)).map_or(max, |delay| delay.min(max));
+                    source.retry_time = SystemTime::now().checked_add(delay);
+                    source.last_error = Some(e.clone());
+
+                    if !self.listeners.is_empty() {
+                        let event = LoadFailureEvent { url, error: e.clone(), duration };
+                        self.listeners.iter().for_each(|listener| listener.on_load_failure(&event));
+                    }
+
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
 
-        if let Ok(value) = result {
-            let expire = load_time + Duration::new(value, 0);
-            self.expire_time = Some(expire);
+        self.load_report = report;
+
+        #[cfg(feature = "metrics")]
+        telemetry::record_sources(&self.sources);
+
+        result
+    }
+
+    fn add_fetched_key_set(&mut self, index: usize, fetched: FetchedKeySet) -> LoadReport {
+        let source = &mut self.sources[index];
+        source.load_time = Some(fetched.load_time);
+
+        if let Some(value) = fetched.max_age {
+            source.expire_time = Some(fetched.load_time + Duration::new(value, 0));
             let refresh_time = (value as f64 * self.refresh_interval) as u64;
-            let refresh = load_time + Duration::new(refresh_time, 0);
-            self.refresh_time = Some(refresh);
+            source.refresh_time = Some(fetched.load_time + Duration::new(refresh_time, 0));
+        }
+
+        self.add_raw_jwks(fetched.jwks, Some(index))
+    }
+
+    /// Add the keys of a JWKS document, published by source `source` if any.
+    ///
+    /// Entries that cannot be parsed, are not supported or conflict with the key of another source
+    /// are skipped and listed in the returned report.
+    fn add_raw_jwks(&mut self, jwks: RawJwtKeys, source: Option<usize>) -> LoadReport {
+        let mut report = LoadReport::default();
+
+        for value in jwks.keys.into_iter() {
+            let kid = value.get("kid").and_then(Value::as_str).map(str::to_string);
+
+            let result = serde_json::from_value::<JwtKey>(value).map_err(|e| err_jwk("Failed to parse key").with_source(e)).and_then(|key| match source {
+                Some(source) => self.add_source_key(source, &key),
+                None => self.insert_key(&key),
+            });
+
+            match result {
+                Ok(kid) => report.loaded.push(kid),
+                Err(reason) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(kid = ?kid, "Skipped key: {}", reason);
+
+                    report.skipped.push(SkippedKey { kid, reason })
+                }
+            }
+        }
+
+        if let Some(source) = source {
+            let previous = std::mem::replace(&mut self.sources[source].kids, report.loaded.clone());
+            self.remove_unpublished_keys(previous);
+        }
+
+        report
+    }
+
+    /// Add a key published by a source, unless another source publishes different key material
+    /// under the same `kid`
+    fn add_source_key(&mut self, source: usize, key: &JwtKey) -> Result<String, Error> {
+        let kid = self.key_id(key)?;
+
+        if let Some(existing) = self.keys.get(&kid) {
+            let other = self.sources.iter().enumerate().find(|(index, other)| *index != source && other.kids.contains(&kid));
+
+            if let Some((_, other)) = other {
+                if PublicKey::from_jwt_key(key)? != existing.public_key {
+                    return Err(err_key(format!("Key id is published with different key material by {}", other.url)).with_kid(kid));
+                }
+                return Ok(kid);
+            }
         }
 
-        let jwks = response.json::<JwtKeys>().await.map_err(|_| err_int("Failed to parse keys"))?;
+        self.insert_key(key)
+    }
 
-        jwks.keys.iter().for_each(|k| self.add_key(k));
+    /// Remove keys of `kids` that no source publishes anymore
+    fn remove_unpublished_keys(&mut self, kids: Vec<String>) {
+        for kid in kids {
+            if !self.sources.iter().any(|source| source.kids.contains(&kid)) {
+                if let Some(key) = self.keys.remove(&kid) {
+                    if let Some(thumbprint) = key.thumbprint {
+                        self.unindex_thumbprint(&thumbprint, &kid);
+                    }
+                    self.clear_verify_cache();
+                }
+            }
+        }
+    }
+
+    /// Save the keys and their load metadata (URL, load, expire and refresh times) as JSON.
+    ///
+    /// The snapshot is `{"url": ..., "load_time": ..., "expire_time": ..., "refresh_time": ..., "jwks": {"keys": [...]}}`
+    /// with times in seconds since UNIX epoch. Restore it with `restore_snapshot`.
+    pub fn to_snapshot(&self) -> Result<String, Error> {
+        let mut keys: Vec<&StoredKey> = self.keys.values().collect();
+        keys.sort_by(|a, b| a.jwk.kid.cmp(&b.jwk.kid));
+
+        let keys = keys
+            .into_iter()
+            .map(|key| serde_json::to_value(&key.jwk))
+            .collect::<Result<Vec<Value>, _>>()
+            .map_err(|e| err_int("Failed to serialize keys").with_source(e))?;
+
+        let snapshot = Snapshot {
+            url: self.key_set_url().to_string(),
+            load_time: self.load_time().and_then(to_seconds),
+            expire_time: self.expire_time().and_then(to_seconds),
+            refresh_time: self.refresh_time().and_then(to_seconds),
+            sources: if self.sources.len() > 1 {
+                self.sources
+                    .iter()
+                    .map(|source| SourceSnapshot {
+                        url: source.url.clone(),
+                        load_time: source.load_time.and_then(to_seconds),
+                        expire_time: source.expire_time.and_then(to_seconds),
+                        refresh_time: source.refresh_time.and_then(to_seconds),
+                        kids: source.kids.clone(),
+                    })
+                    .collect()
+            } else {
+                vec![]
+            },
+            jwks: RawJwtKeys { keys },
+        };
+
+        serde_json::to_string(&snapshot).map_err(|e| err_int("Failed to serialize snapshot").with_source(e))
+    }
+
+    /// Replace the keys and load metadata with a snapshot saved by `to_snapshot`.
+    ///
+    /// Settings of the store (timeouts, caches, trust anchors...) are kept. Keys that can no longer
+    /// be added are skipped and listed in the load report. `keys_expired` and `should_refresh` tell
+    /// whether the restored keys should be refreshed.
+    pub fn restore_snapshot(&mut self, snapshot: &str) -> Result<(), Error> {
+        let snapshot = serde_json::from_str::<Snapshot>(snapshot).map_err(|e| err_jwk("Failed to parse snapshot").with_source(e))?;
+
+        self.clear_keys();
+
+        let single_source = snapshot.sources.is_empty() && !snapshot.url.is_empty();
+
+        self.sources = snapshot
+            .sources
+            .into_iter()
+            .map(|source| KeySource {
+                url: source.url,
+                load_time: source.load_time.map(from_seconds),
+                expire_time: source.expire_time.map(from_seconds),
+                refresh_time: source.refresh_time.map(from_seconds),
+                kids: source.kids,
+                failures: 0,
+                retry_time: None,
+                last_error: None,
+            })
+            .collect();
+
+        self.load_report = self.add_raw_jwks(snapshot.jwks, None);
+
+        if single_source {
+            self.sources.push(KeySource {
+                url: snapshot.url,
+                load_time: snapshot.load_time.map(from_seconds),
+                expire_time: snapshot.expire_time.map(from_seconds),
+                refresh_time: snapshot.refresh_time.map(from_seconds),
+                kids: self.load_report.loaded.clone(),
+                failures: 0,
+                retry_time: None,
+                last_error: None,
+            });
+        }
 
         Ok(())
     }
 
-    fn cache_max_age(response: &mut Response) -> Result<u64, ()> {
-        let header = response.headers().get("cache-control").ok_or(())?;
+    /// Create a key store from a snapshot saved by `to_snapshot`
+    pub fn from_snapshot(snapshot: &str) -> Result<KeyStore, Error> {
+        let mut key_store = KeyStore::new();
 
-        let header_text = header.to_str().map_err(|_| ())?;
+        key_store.restore_snapshot(snapshot)?;
 
-        let re = Regex::new("max-age\\s*=\\s*(\\d+)").map_err(|_| ())?;
+        Ok(key_store)
+    }
+
+    /// Keys loaded and skipped by the last `load_keys` or `restore_snapshot`
+    pub fn load_report(&self) -> &LoadReport {
+        &self.load_report
+    }
+
+    /// Keep using keys up to `max_staleness` after they expire when they cannot be refreshed.
+    ///
+    /// Keys are never removed because a refresh failed; this limit only matters for
+    /// `keys_stale` and, if enabled, `set_refuse_stale_keys`. The default is 0.
+    pub fn set_max_staleness(&mut self, max_staleness: Duration) {
+        self.max_staleness = max_staleness;
+    }
+
+    pub fn max_staleness(&self) -> Duration {
+        self.max_staleness
+    }
+
+    /// Refuse tokens whose key comes only from sources whose keys expired more than
+    /// `max_staleness` ago. Disabled by default. Keys added with `add_key` are never stale.
+    pub fn set_refuse_stale_keys(&mut self, refuse: bool) {
+        self.refuse_stale_keys = refuse;
+    }
+
+    pub fn refuse_stale_keys(&self) -> bool {
+        self.refuse_stale_keys
+    }
 
-        let captures = re.captures(header_text).ok_or(())?;
+    /// Delay before retrying a failed source, doubled after each consecutive failure up to `max`.
+    ///
+    /// The default is 1 second, up to 5 minutes.
+    pub fn set_retry_backoff(&mut self, initial: Duration, max: Duration) {
+        self.retry_backoff = (initial, max);
+    }
 
-        let capture = captures.get(1).ok_or(())?;
+    /// True if keys of a source expired more than `max_staleness` before `time`
+    ///
+    /// None if keys do not have an expiration time
+    pub fn keys_stale_time(&self, time: SystemTime) -> Option<bool> {
+        self.expire_time()?;
+
+        Some(self.sources.iter().any(|source| source.stale_time(self.max_staleness, time)))
+    }
+
+    /// Same as `keys_stale_time` using the system time
+    pub fn keys_stale(&self) -> Option<bool> {
+        self.keys_stale_time(SystemTime::now())
+    }
+
+    /// Refuse `key` if stale keys are refused and every source publishing it is stale
+    fn check_stale(&self, key: &StoredKey, time: SystemTime) -> Result<(), Error> {
+        if !self.refuse_stale_keys {
+            return Ok(());
+        }
+
+        let mut sources = self.sources.iter().filter(|source| source.kids.contains(&key.jwk.kid)).peekable();
+
+        if sources.peek().is_some() && sources.all(|source| source.stale_time(self.max_staleness, time)) {
+            return Err(err_key("Key is stale").with_kid(&key.jwk.kid));
+        }
+
+        Ok(())
+    }
+
+    /// Notify `listener` of loads and verification failures
+    pub fn add_listener(&mut self, listener: Arc<dyn KeyStoreListener>) {
+        self.listeners.push(listener);
+    }
 
-        let text = capture.as_str();
+    pub fn clear_listeners(&mut self) {
+        self.listeners.clear();
+    }
 
-        let value = text.parse::<u64>().map_err(|_| ())?;
+    /// Maximum time allowed to download the key set. There is no timeout by default.
+    pub fn set_load_timeout(&mut self, timeout: Duration) {
+        self.load_timeout = Some(timeout);
+    }
 
-        Ok(value)
+    /// Time allowed to download the key set
+    pub fn load_timeout(&self) -> Option<Duration> {
+        self.load_timeout
     }
 
     /// Fetch a key by key id (KID)
     pub fn key_by_id(&self, kid: &str) -> Option<&JwtKey> {
-        self.keys.iter().find(|k| k.kid == kid)
+        self.keys.get(kid).map(|k| &k.jwk)
     }
 
     /// Number of keys in keystore
@@ -157,27 +1121,165 @@ impl KeyStore {
         self.keys.len()
     }
 
+    /// Fetch a key by its RFC 7638 thumbprint
+    pub fn key_by_thumbprint(&self, thumbprint: &str) -> Option<&JwtKey> {
+        let kid = self.thumbprints.get(thumbprint)?;
+
+        self.key_by_id(kid)
+    }
+
     /// Manually add a key to the keystore
-    pub fn add_key(&mut self, key: &JwtKey) {
-        self.keys.push(key.clone());
+    ///
+    /// The key material is decoded once here so that it does not need to be decoded on every
+    /// verification. A key with the same `kid` is replaced.
+    ///
+    /// A key without `kid` is rejected unless `set_thumbprint_kid(true)` was called, in which case
+    /// its thumbprint is used as `kid`.
+    pub fn add_key(&mut self, key: &JwtKey) -> Result<(), Error> {
+        self.insert_key(key).map(|_| ())
     }
 
-    fn decode_segments(&self, token: &str) -> Result<(Header, Payload, Signature, HeaderBody), Error> {
-        let raw_segments: Vec<&str> = token.split(".").collect();
-        if raw_segments.len() != 3 {
-            return Err(err_inv("JWT does not have 3 segments"));
+    /// `kid` under which a key is stored
+    fn key_id(&self, key: &JwtKey) -> Result<String, Error> {
+        match (key.kid.is_empty(), self.thumbprint_kid) {
+            (false, _) => Ok(key.kid.clone()),
+            (true, true) => key.thumbprint().map_err(|_| err_key("Key does not have a key id")),
+            (true, false) => Err(err_key("Key does not have a key id")),
+        }
+    }
+
+    /// Add a key and return its `kid`
+    fn insert_key(&mut self, key: &JwtKey) -> Result<String, Error> {
+        let public_key = PublicKey::from_jwt_key(key)?;
+
+        if !self.trust_anchors.is_empty() {
+            let x5c = key.x5c.as_ref().ok_or_else(|| err_cer("Key does not have a certificate chain").with_kid(&key.kid))?;
+            let chain = x509::decode_chain(x5c)?;
+
+            x509::verify_chain(&chain, &self.trust_anchors, SystemTime::now()).map_err(|e| e.with_kid(&key.kid))?;
         }
 
-        let header_segment = raw_segments[0];
-        let payload_segment = raw_segments[1];
-        let signature_segment = raw_segments[2].to_string();
+        let thumbprint = key.thumbprint().ok();
 
-        let header = Header::new(decode_segment::<Value>(header_segment).or(Err(err_hea("Failed to decode header")))?);
-        let payload = Payload::new(decode_segment::<Value>(payload_segment).or(Err(err_pay("Failed to decode payload")))?);
+        let mut jwk = key.clone();
+
+        if jwk.kid.is_empty() {
+            jwk.kid = match (&thumbprint, self.thumbprint_kid) {
+                (Some(thumbprint), true) => thumbprint.clone(),
+                _ => return Err(err_key("Key does not have a key id")),
+            };
+        }
+
+        if let Some(thumbprint) = &thumbprint {
+            self.thumbprints.insert(thumbprint.clone(), jwk.kid.clone());
+        }
+
+        let kid = jwk.kid.clone();
+
+        let replaced = self.keys.insert(kid.clone(), StoredKey { jwk, public_key, thumbprint: thumbprint.clone() });
+
+        if let Some(replaced_thumbprint) = replaced.and_then(|k| k.thumbprint) {
+            if Some(&replaced_thumbprint) != thumbprint.as_ref() {
+                self.unindex_thumbprint(&replaced_thumbprint, &kid);
+            }
+        }
+
+        self.clear_verify_cache();
+
+        Ok(kid)
+    }
+
+    /// Remove the thumbprint of a key that is no longer stored under `kid`. Keys with the same key
+    /// material share a thumbprint: it then leads to one of the remaining keys.
+    fn unindex_thumbprint(&mut self, thumbprint: &str, kid: &str) {
+        if self.thumbprints.get(thumbprint).map(String::as_str) != Some(kid) {
+            return;
+        }
+
+        match self.keys.values().find(|key| key.thumbprint.as_deref() == Some(thumbprint)) {
+            Some(key) => {
+                let other_kid = key.jwk.kid.clone();
+                self.thumbprints.insert(thumbprint.to_string(), other_kid);
+            }
+            None => {
+                self.thumbprints.remove(thumbprint);
+            }
+        }
+    }
+
+    /// Add a trusted root certificate (DER).
+    ///
+    /// Once a trust anchor is configured, keys are only added if their `x5c` chain leads to a trust
+    /// anchor, and tokens without `kid` may be verified with the `x5c` chain of their header, if its
+    /// leaf certificate holds the public key of a stored key.
+    pub fn add_trust_anchor_der(&mut self, der: &[u8]) -> Result<(), Error> {
+        x509::trust_anchor(der)?;
+
+        self.trust_anchors.push(der.to_vec());
+
+        Ok(())
+    }
+
+    /// Add a trusted root certificate (PEM `CERTIFICATE`)
+    pub fn add_trust_anchor_pem(&mut self, pem: &str) -> Result<(), Error> {
+        let (label, der) = decode_pem(pem)?;
+
+        if label != "CERTIFICATE" {
+            return Err(err_cer("Unsupported PEM type").with_expected("CERTIFICATE", label));
+        }
+
+        self.add_trust_anchor_der(&der)
+    }
+
+    /// Remove all trust anchors. Certificate chains are then no longer validated.
+    pub fn clear_trust_anchors(&mut self) {
+        self.trust_anchors.clear();
+    }
+
+    /// Use the RFC 7638 thumbprint as `kid` of keys that do not have one. Disabled by default.
+    ///
+    /// Applies to keys added after this call.
+    pub fn set_thumbprint_kid(&mut self, enabled: bool) {
+        self.thumbprint_kid = enabled;
+    }
+
+    /// True if the thumbprint is used as `kid` of keys that do not have one
+    pub fn thumbprint_kid(&self) -> bool {
+        self.thumbprint_kid
+    }
+
+    /// Remember successful verifications to skip signature verification of repeated tokens.
+    ///
+    /// Up to `capacity` tokens (identified by their SHA-256 hash) are kept, least recently used
+    /// first out. A token is remembered until the earlier of its `exp` claim or `ttl`. The cache
+    /// is cleared whenever keys are added, loaded or cleared. Expiry and not-before are still
+    /// checked on every verification.
+    pub fn set_verify_cache(&mut self, capacity: usize, ttl: Duration) {
+        self.verify_cache = Some(LockedCache::new(capacity, ttl));
+    }
+
+    /// Stop caching successful verifications
+    pub fn disable_verify_cache(&mut self) {
+        self.verify_cache = None;
+    }
+
+    /// Start over with an empty cache. The previous one may still be used by clones of the store
+    /// (ie: snapshots of a `SharedKeyStore`) that have the previous keys.
+    fn clear_verify_cache(&mut self) {
+        if let Some(cache) = &mut self.verify_cache {
+            *cache = cache.renewed();
+        }
+    }
+
+    fn decode_segments(&self, token: &str) -> Result<(Header, Payload, Signature, HeaderBody), Error> {
+        let (header_segment, payload_segment, signature_segment) = split_token(token)?;
+
+        let header = Header::new(decode_segment::<Value>(header_segment).map_err(|e| err_hea("Failed to decode header").with_source(e))?);
+        let payload = Payload::new(decode_segment::<Value>(payload_segment).map_err(|e| err_pay("Failed to decode payload").with_source(e))?);
 
         let body = format!("{}.{}", header_segment, payload_segment);
 
-        Ok((header, payload, signature_segment, body))
+        Ok((header, payload, signature_segment.to_string(), body))
     }
 
     pub fn decode(&self, token: &str) -> Result<Jwt, Error> {
@@ -186,40 +1288,231 @@ impl KeyStore {
         Ok(Jwt::new(header, payload, signature))
     }
 
+    /// Decode a JWT token without parsing the header and payload into JSON values.
+    ///
+    /// Use `RawJwt::header` and `RawJwt::payload` to deserialize the segments into user types.
+    pub fn decode_raw(&self, token: &str) -> Result<RawJwt, Error> {
+        decode_raw_token(token)
+    }
+
     pub fn verify_time(&self, token: &str, time: SystemTime) -> Result<Jwt, Error> {
+        self.verify_time_with(token, time, None, |_| Ok(()))
+    }
+
+    /// `verify_time` followed by `check`, notified and counted as one verification of `issuer`
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub(crate) fn verify_time_with<F>(&self, token: &str, time: SystemTime, issuer: Option<&str>, check: F) -> Result<Jwt, Error>
+    where
+        F: FnOnce(&Jwt) -> Result<(), Error>,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = verify_span().entered();
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+
+        let result = self.verify_token_time(token, time).and_then(|jwt| check(&jwt).map(|_| jwt));
+
+        #[cfg(feature = "metrics")]
+        telemetry::record_verification(result.as_ref().err(), issuer, start.elapsed());
+
+        self.notify_verify(result)
+    }
+
+    fn verify_token_time(&self, token: &str, time: SystemTime) -> Result<Jwt, Error> {
         let (header, payload, signature, body) = self.decode_segments(token)?;
 
-        if header.alg() != Some("RS256") {
-            return Err(err_inv("Unsupported algorithm"));
+        #[cfg(feature = "tracing")]
+        record_header(header.kid(), header.alg());
+
+        let claims = RegisteredClaims::from_payload(&payload)?;
+
+        self.verify_signature_cached(&RegisteredHeader::from_header(&header), &body, &signature, &claims, time)?;
+
+        if claims.expired_time(time)? {
+            return Err(err_exp("Token expired").with_claim("exp"));
         }
+        if claims.early_time(time)? {
+            return Err(err_nbf("Too early to use token (nbf)").with_claim("nbf"));
+        }
+
+        Ok(Jwt::new(header, payload, signature))
+    }
+
+    /// Same as `verify_time` but returns a `RawJwt`.
+    ///
+    /// Only the registered fields needed for verification (`alg`, `kid`, `x5t`, `x5t#S256`, `exp` and `nbf`) are read.
+    pub fn verify_raw_time(&self, token: &str, time: SystemTime) -> Result<RawJwt, Error> {
+        #[cfg(feature = "tracing")]
+        let _span = verify_span().entered();
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+
+        let result = self.verify_raw_token_time(token, time);
+
+        #[cfg(feature = "metrics")]
+        telemetry::record_verification(result.as_ref().err(), None, start.elapsed());
+
+        self.notify_verify(result)
+    }
+
+    fn verify_raw_token_time(&self, token: &str, time: SystemTime) -> Result<RawJwt, Error> {
+        let (header_segment, payload_segment, signature_segment) = split_token(token)?;
 
-        let kid = header.kid().ok_or(err_key("No key id"))?;
+        let jwt = decode_raw_segments(header_segment, payload_segment, signature_segment)?;
 
-        let key = self.key_by_id(kid).ok_or(err_key("JWT key does not exists"))?;
+        let header = jwt.header::<RegisteredHeader>()?;
 
-        let e = decode_config(&key.e, URL_SAFE_NO_PAD).or(Err(err_cer("Failed to decode exponent")))?;
-        let n = decode_config(&key.n, URL_SAFE_NO_PAD).or(Err(err_cer("Failed to decode modulus")))?;
+        #[cfg(feature = "tracing")]
+        record_header(header.kid.as_deref(), header.alg.as_deref());
 
-        verify_signature(&e, &n, &body, &signature)?;
+        let claims = jwt.payload::<RegisteredClaims>()?;
 
-        let jwt = Jwt::new(header, payload, signature);
+        let body = &token[..header_segment.len() + 1 + payload_segment.len()];
 
-        if jwt.expired_time(time).unwrap_or(false) {
-            return Err(err_exp("Token expired"));
+        self.verify_signature_cached(&header, body, signature_segment, &claims, time)?;
+
+        if claims.expired_time(time)? {
+            return Err(err_exp("Token expired").with_claim("exp"));
         }
-        if jwt.early_time(time).unwrap_or(false) {
-            return Err(err_nbf("Too early to use token (nbf)"));
+        if claims.early_time(time)? {
+            return Err(err_nbf("Too early to use token (nbf)").with_claim("nbf"));
         }
 
         Ok(jwt)
     }
 
+    /// Same as `verify` but returns a `RawJwt`, avoiding the intermediate JSON values.
+    pub fn verify_raw(&self, token: &str) -> Result<RawJwt, Error> {
+        self.verify_raw_time(token, SystemTime::now())
+    }
+
+    fn notify_verify<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        notify_verify(&self.listeners, result)
+    }
+
+    /// Verify the signature unless the token is in the verification cache.
+    ///
+    /// The claims are only read once the signature is verified, to cache the token until its `exp`.
+    fn verify_signature_cached(&self, header: &RegisteredHeader, body: &str, signature: &str, claims: &RegisteredClaims, time: SystemTime) -> Result<(), Error> {
+        let cache = match &self.verify_cache {
+            // Cached tokens do not record their key, so stale keys must be checked on every verification
+            Some(_) if self.refuse_stale_keys && self.keys_stale_time(time) == Some(true) => return self.verify_signature(header, body, signature, time),
+            Some(cache) => cache,
+            None => return self.verify_signature(header, body, signature, time),
+        };
+
+        let hash = token_hash(body, signature);
+
+        // A poisoned cache is treated as a cache miss
+        if let Ok(mut cache) = cache.lock() {
+            if cache.contains(&hash, time) {
+                return Ok(());
+            }
+        }
+
+        self.verify_signature(header, body, signature, time)?;
+
+        // An out of range `exp` is rejected after this, the token is not cached
+        if let (Ok(expiry), Ok(mut cache)) = (claims.expiry(), cache.lock()) {
+            cache.insert(hash, expiry, time);
+        }
+
+        Ok(())
+    }
+
+    /// Select the key for the token and verify its signature.
+    ///
+    /// The key is selected by `kid`, then by the leaf of the header `x5c` chain if trust anchors are
+    /// configured, then by `x5t#S256` or `x5t`. Without any of them, compatible keys are tried if enabled with
+    /// `set_verify_without_kid`.
+    fn verify_signature(&self, header: &RegisteredHeader, body: &str, signature: &str, time: SystemTime) -> Result<(), Error> {
+        let alg = match header.alg.as_deref() {
+            Some(alg) if ALGORITHMS.contains(&alg) => alg,
+            alg => return Err(err_inv("Unsupported algorithm").with_expected(ALGORITHMS.join(", "), alg.unwrap_or("none"))),
+        };
+
+        if let Some(kid) = header.kid.as_deref() {
+            let key = self.keys.get(kid).ok_or_else(|| err_key("JWT key does not exists").with_kid(kid))?;
+
+            self.check_stale(key, time)?;
+
+            return verify_with_key(key, alg, body, signature);
+        }
+
+        if let (Some(x5c), false) = (&header.x5c, self.trust_anchors.is_empty()) {
+            let chain = x509::decode_chain(x5c)?;
+
+            x509::verify_chain(&chain, &self.trust_anchors, time)?;
+            x509::check_thumbprints(&chain[0], header.x5t.as_deref(), header.x5t_s256.as_deref())?;
+
+            let public_key = x509::certificate_public_key(&chain[0])?;
+
+            let key = self.keys.values().find(|k| k.public_key == public_key).ok_or_else(|| err_key("No key matches the x5c certificate"))?;
+
+            self.check_stale(key, time)?;
+
+            return verify_with_key(key, alg, body, signature);
+        }
+
+        if let Some(x5t) = header.x5t_s256.as_deref() {
+            let key = self.keys.values().find(|k| k.jwk.x5t_s256.as_deref() == Some(x5t)).ok_or_else(|| err_key("No key matches the x5t#S256 thumbprint"))?;
+
+            self.check_stale(key, time)?;
+
+            return verify_with_key(key, alg, body, signature);
+        }
+
+        if let Some(x5t) = header.x5t.as_deref() {
+            let key = self.keys.values().find(|k| k.jwk.x5t.as_deref() == Some(x5t)).ok_or_else(|| err_key("No key matches the x5t thumbprint"))?;
+
+            self.check_stale(key, time)?;
+
+            return verify_with_key(key, alg, body, signature);
+        }
+
+        let max_keys = self.kidless_max_keys.ok_or_else(|| err_key("No key id"))?;
+
+        let candidates: Vec<&StoredKey> = self
+            .keys
+            .values()
+            .filter(|k| k.public_key.supports(alg) && k.jwk.check_verify(alg).is_ok() && self.check_stale(k, time).is_ok())
+            .collect();
+
+        if candidates.is_empty() {
+            return Err(err_key("No key compatible with the token algorithm").with_alg(alg));
+        }
+        if candidates.len() > max_keys {
+            return Err(err_key("Too many keys compatible with the token algorithm").with_alg(alg).with_expected(max_keys.to_string(), candidates.len().to_string()));
+        }
+
+        if candidates.iter().any(|key| verify_with_key(key, alg, body, signature).is_ok()) {
+            Ok(())
+        } else {
+            Err(err_cer("Signature does not match any key").with_alg(alg))
+        }
+    }
+
+    /// Verify tokens that do not have a `kid` (nor `x5t`) by trying each key compatible with the
+    /// token algorithm. Verification fails if more than `max_keys` keys are compatible.
+    ///
+    /// Disabled by default.
+    pub fn set_verify_without_kid(&mut self, max_keys: usize) {
+        self.kidless_max_keys = Some(max_keys);
+    }
+
+    /// Require a `kid` (or `x5t`) in the token header. This is the default.
+    pub fn disable_verify_without_kid(&mut self) {
+        self.kidless_max_keys = None;
+    }
+
     /// Verify a JWT token.
     /// If the token is valid, it is returned.
     ///
     /// A token is considered valid if:
     /// * Is well formed
     /// * Has a `kid` field that matches a public signature `kid
+    /// * Is signed with `RS256`, `RS384`, `RS512`, `PS256`, `PS384`, `PS512`, `ES256`, `ES384` or `EdDSA`
+    /// * The key's `use`, `key_ops` and `alg` allow verifying the token
     /// * Signature matches public key
     /// * It is not expired
     /// * The `nbf` is not set to before now
@@ -229,17 +1522,14 @@ impl KeyStore {
 
     /// Time at which the keys were last refreshed
     pub fn last_load_time(&self) -> Option<SystemTime> {
-        self.load_time
+        self.load_time()
     }
 
     /// True if the keys are expired and should be refreshed
     ///
     /// None if keys do not have an expiration time
     pub fn keys_expired(&self) -> Option<bool> {
-        match self.expire_time {
-            Some(expire) => Some(expire <= SystemTime::now()),
-            None => None,
-        }
+        self.expire_time().map(|expire| expire <= SystemTime::now())
     }
 
     /// Specifies the interval (as a fraction) when the key store should refresh it's key.
@@ -256,20 +1546,20 @@ impl KeyStore {
         self.refresh_interval
     }
 
-    /// The time at which the keys were loaded
+    /// The time at which the keys were loaded (the latest of all key sources)
     /// None if the keys were never loaded via `load_keys` or `load_keys_from`.
     pub fn load_time(&self) -> Option<SystemTime> {
-        self.load_time
+        self.sources.iter().filter_map(|source| source.load_time).max()
     }
 
-    /// Get the time at which the keys are considered expired
+    /// Get the time at which the keys are considered expired (the earliest of all key sources)
     pub fn expire_time(&self) -> Option<SystemTime> {
-        self.expire_time
+        self.sources.iter().filter_map(|source| source.expire_time).min()
     }
 
-    /// time at which keys should be refreshed.
+    /// time at which keys should be refreshed (the earliest of all key sources).
     pub fn refresh_time(&self) -> Option<SystemTime> {
-        self.refresh_time
+        self.sources.iter().filter_map(|source| source.refresh_time).min()
     }
 
     /// Returns `Option<true>` if keys should be refreshed based on the given `current_time`.
@@ -277,7 +1567,7 @@ impl KeyStore {
     /// None is returned if the key store does not have a refresh time available. For example, the
     /// `load_keys` function was not called or the HTTP server did not provide a  
     pub fn should_refresh_time(&self, current_time: SystemTime) -> Option<bool> {
-        if let Some(refresh_time) = self.refresh_time {
+        if let Some(refresh_time) = self.refresh_time() {
             return Some(refresh_time <= current_time);
         }
 
@@ -293,21 +1583,217 @@ impl KeyStore {
     }
 }
 
-fn verify_signature(e: &Vec<u8>, n: &Vec<u8>, message: &str, signature: &str) -> Result<(), Error> {
-    let pkc = RsaPublicKeyComponents { e, n };
+/// JWKS document downloaded from a key source
+pub(crate) struct FetchedKeySet {
+    load_time: SystemTime,
+    /// `max-age` of the `Cache-Control` header
+    max_age: Option<u64>,
+    jwks: RawJwtKeys,
+}
+
+/// Trace the outcome of a verification and notify `listeners` of a failure
+pub(crate) fn notify_verify<T>(listeners: &[Arc<dyn KeyStoreListener>], result: Result<T, Error>) -> Result<T, Error> {
+    #[cfg(feature = "tracing")]
+    match &result {
+        Ok(_) => tracing::debug!(outcome = "verified", "Token verified"),
+        // Only the reason: expected and actual values may come from the token
+        Err(error) => tracing::debug!(outcome = "rejected", typ = ?error.typ, claim = ?error.claim(), "Token rejected: {}", error.msg),
+    }
+
+    if let Err(error) = &result {
+        listeners.iter().for_each(|listener| listener.on_verify_failure(error));
+    }
 
-    let message_bytes = &message.as_bytes().to_vec();
-    let signature_bytes = decode_config(&signature, URL_SAFE_NO_PAD).or(Err(err_sig("Could not base64 decode signature")))?;
+    result
+}
+
+/// Span of a token verification. Header fields are recorded once decoded.
+#[cfg(feature = "tracing")]
+pub(crate) fn verify_span() -> tracing::Span {
+    tracing::debug_span!("jwt_verify", kid = tracing::field::Empty, alg = tracing::field::Empty)
+}
+
+#[cfg(feature = "tracing")]
+fn record_header(kid: Option<&str>, alg: Option<&str>) {
+    let span = tracing::Span::current();
+
+    if let Some(kid) = kid {
+        span.record("kid", kid);
+    }
+    if let Some(alg) = alg {
+        span.record("alg", alg);
+    }
+}
+
+/// Outcome of the download of a source's key set
+pub(crate) struct SourceLoad {
+    url: String,
+    duration: Duration,
+    result: Result<FetchedKeySet, Error>,
+}
+
+/// Download the key set of the source at `url`
+pub(crate) async fn fetch_source(url: String, client: &Client, timeout: Option<Duration>) -> SourceLoad {
+    let start = Instant::now();
+
+    #[cfg(feature = "tracing")]
+    let result = {
+        use tracing::Instrument;
+
+        let span = tracing::info_span!("jwks_fetch", url = %url);
+        fetch_key_set(&url, client, timeout).instrument(span).await
+    };
+    #[cfg(not(feature = "tracing"))]
+    let result = fetch_key_set(&url, client, timeout).await;
+
+    let duration = start.elapsed();
+
+    #[cfg(feature = "metrics")]
+    telemetry::record_fetch(&url, result.as_ref().err(), duration);
+
+    #[cfg(feature = "tracing")]
+    if let Err(error) = &result {
+        tracing::warn!(url = %url, typ = ?error.typ, status = ?error.status(), ?duration, "Could not load key set: {}", error);
+    }
+
+    SourceLoad { url, duration, result }
+}
+
+/// Download the key set at `url`
+async fn fetch_key_set(url: &str, client: &Client, timeout: Option<Duration>) -> Result<FetchedKeySet, Error> {
+    let mut request = client.get(url);
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+
+    let mut response = request.send().await.map_err(download_error)?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(err_sta("JWKS server returned an error status").with_status(status.as_u16()));
+    }
+
+    let load_time = SystemTime::now();
+
+    let max_age = cache_max_age(&mut response).ok();
+
+    let body = response.bytes().await.map_err(download_error)?;
+
+    let jwks = serde_json::from_slice::<RawJwtKeys>(&body).map_err(|e| err_jwk("Failed to parse keys").with_source(e))?;
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(status = status.as_u16(), ?max_age, keys = jwks.keys.len(), "Downloaded key set");
+
+    Ok(FetchedKeySet { load_time, max_age, jwks })
+}
+
+fn cache_max_age(response: &mut Response) -> Result<u64, ()> {
+    let header = response.headers().get("cache-control").ok_or(())?;
+
+    let header_text = header.to_str().map_err(|_| ())?;
+
+    let re = Regex::new("max-age\\s*=\\s*(\\d+)").map_err(|_| ())?;
+
+    let captures = re.captures(header_text).ok_or(())?;
+
+    let capture = captures.get(1).ok_or(())?;
+
+    let text = capture.as_str();
+
+    let value = text.parse::<u64>().map_err(|_| ())?;
+
+    Ok(value)
+}
+
+/// Classify a failure to download the key set
+fn download_error(error: reqwest::Error) -> Error {
+    let result = if error.is_timeout() {
+        err_tim("Timed out while downloading JWKS")
+    } else if is_tls_error(&error) {
+        err_tls("TLS failure while downloading JWKS")
+    } else {
+        err_con("Could not download JWKS")
+    };
+
+    result.with_source(error)
+}
+
+/// True if a connection failed during the TLS handshake or certificate validation: one of the
+/// causes of the error is an error of the TLS backend.
+fn is_tls_error(error: &reqwest::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+
+    while let Some(error) = source {
+        if is_tls_backend_error(error) {
+            return true;
+        }
+        // `io::Error` skips the error it wraps and returns the source of that error instead
+        source = match error.downcast_ref::<std::io::Error>() {
+            Some(io_error) => io_error.get_ref().map(|inner| inner as &(dyn std::error::Error + 'static)),
+            None => error.source(),
+        };
+    }
+
+    false
+}
+
+#[allow(unused_variables)]
+fn is_tls_backend_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    #[cfg(feature = "native-tls")]
+    if error.is::<native_tls::Error>() {
+        return true;
+    }
+    #[cfg(feature = "rustls-tls")]
+    if error.is::<rustls::Error>() {
+        return true;
+    }
+
+    false
+}
+
+fn to_seconds(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|duration| duration.as_secs())
+}
+
+fn from_seconds(seconds: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+fn verify_with_key(key: &StoredKey, alg: &str, body: &str, signature: &str) -> Result<(), Error> {
+    let kid = key.jwk.kid.as_str();
+
+    key.jwk.check_verify(alg).map_err(|e| e.with_kid(kid).with_alg(alg))?;
+
+    key.public_key.verify(alg, body, signature).map_err(|e| e.with_kid(kid).with_alg(alg))
+}
+
+/// Decode a token without verifying it
+pub(crate) fn decode_raw_token(token: &str) -> Result<RawJwt, Error> {
+    let (header_segment, payload_segment, signature_segment) = split_token(token)?;
+
+    decode_raw_segments(header_segment, payload_segment, signature_segment)
+}
+
+fn split_token(token: &str) -> Result<(&str, &str, &str), Error> {
+    let raw_segments: Vec<&str> = token.split('.').collect();
+    if raw_segments.len() != 3 {
+        return Err(err_inv("JWT does not have 3 segments"));
+    }
+
+    Ok((raw_segments[0], raw_segments[1], raw_segments[2]))
+}
 
-    let result = pkc.verify(&RSA_PKCS1_2048_8192_SHA256, &message_bytes, &signature_bytes);
+fn decode_raw_segments(header_segment: &str, payload_segment: &str, signature_segment: &str) -> Result<RawJwt, Error> {
+    let header = decode_config(header_segment, URL_SAFE_NO_PAD).map_err(|e| err_hea("Failed to decode header").with_source(e))?;
+    let payload = decode_config(payload_segment, URL_SAFE_NO_PAD).map_err(|e| err_pay("Failed to decode payload").with_source(e))?;
 
-    result.or(Err(err_cer("Signature does not match certificate")))
+    Ok(RawJwt::new(header, payload, signature_segment.to_string()))
 }
 
 fn decode_segment<T: DeserializeOwned>(segment: &str) -> Result<T, Error> {
-    let raw = decode_config(segment, base64::URL_SAFE_NO_PAD).or(Err(err_inv("Failed to decode segment")))?;
+    let raw = decode_config(segment, base64::URL_SAFE_NO_PAD).map_err(|e| err_inv("Failed to decode segment").with_source(e))?;
     let slice = String::from_utf8_lossy(&raw);
-    let decoded: T = serde_json::from_str(&slice).or(Err(err_inv("Failed to decode segment")))?;
+    let decoded: T = serde_json::from_str(&slice).map_err(|e| err_inv("Failed to decode segment").with_source(e))?;
 
     Ok(decoded)
 }
@@ -0,0 +1,197 @@
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING, ECDSA_P384_SHA384_FIXED_SIGNING};
+use serde::Serialize;
+
+use crate::encoder::{encode, SigningKey};
+use crate::error::*;
+use crate::jwt::Header;
+use crate::keyset::{JwtKeys, KeyStore};
+
+/// Set of private keys used to sign tokens, for example to run a local identity provider.
+///
+/// The public half of the keys can be exported as a JWKS document that `KeyStore` can load.
+#[derive(Debug, Default)]
+pub struct PrivateKeySet {
+    keys: Vec<SigningKey>,
+}
+
+impl PrivateKeySet {
+    pub fn new() -> PrivateKeySet {
+        PrivateKeySet { keys: vec![] }
+    }
+
+    /// Add a signing key and return its key id.
+    ///
+    /// Keys without a `kid` are given their RFC 7638 thumbprint as `kid`. HMAC keys must have a
+    /// `kid`. A key with the same `kid` is replaced.
+    pub fn add_key(&mut self, key: SigningKey) -> Result<String, Error> {
+        let key = match (key.kid(), key.public_key()) {
+            (Some(_), _) => key,
+            (None, Some(public_key)) => {
+                let kid = public_key.thumbprint()?;
+                key.with_kid(&kid)
+            }
+            (None, None) => return Err(err_key("HMAC keys require a key id")),
+        };
+
+        let kid = key.kid().unwrap_or_default().to_string();
+
+        self.keys.retain(|k| k.kid() != Some(kid.as_str()));
+        self.keys.push(key);
+
+        Ok(kid)
+    }
+
+    /// Generate an EC key pair for `ES256` (P-256) or `ES384` (P-384) and return its key id
+    pub fn generate_ec(&mut self, alg: &str) -> Result<String, Error> {
+        let signing = match alg {
+            "ES256" => &ECDSA_P256_SHA256_FIXED_SIGNING,
+            "ES384" => &ECDSA_P384_SHA384_FIXED_SIGNING,
+            _ => return Err(err_key("Unsupported algorithm").with_expected("ES256 or ES384", alg)),
+        };
+
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(signing, &SystemRandom::new()).map_err(|_| err_int("Failed to generate EC key"))?;
+
+        self.add_key(SigningKey::from_pkcs8(alg, pkcs8.as_ref())?)
+    }
+
+    /// Generate an Ed25519 key pair (`EdDSA`) and return its key id
+    pub fn generate_ed25519(&mut self) -> Result<String, Error> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).map_err(|_| err_int("Failed to generate Ed25519 key"))?;
+
+        self.add_key(SigningKey::from_pkcs8("EdDSA", pkcs8.as_ref())?)
+    }
+
+    /// Generate an RSA key pair of `bits` bits for `alg` (ie: `RS256`, `PS256`) and return its key id.
+    ///
+    /// Requires the `rsa-keygen` feature.
+    #[cfg(feature = "rsa-keygen")]
+    pub fn generate_rsa(&mut self, alg: &str, bits: usize) -> Result<String, Error> {
+        use rsa::pkcs8::EncodePrivateKey;
+
+        let private_key = rsa::RsaPrivateKey::new(&mut rsa::rand_core::OsRng, bits).map_err(|e| err_key("Failed to generate RSA key").with_source(e))?;
+
+        let pkcs8 = private_key.to_pkcs8_der().map_err(|e| err_int("Failed to encode RSA key").with_source(e))?;
+
+        self.add_key(SigningKey::from_pkcs8(alg, pkcs8.as_bytes())?)
+    }
+
+    /// Fetch a signing key by key id (KID)
+    pub fn key_by_id(&self, kid: &str) -> Option<&SigningKey> {
+        self.keys.iter().find(|k| k.kid() == Some(kid))
+    }
+
+    /// Number of keys in the set
+    pub fn keys_len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Sign a token with the key identified by `kid`
+    pub fn encode<T: Serialize>(&self, kid: &str, header: &Header, claims: &T) -> Result<String, Error> {
+        let key = self.key_by_id(kid).ok_or_else(|| err_key("JWT key does not exists").with_kid(kid))?;
+
+        encode(header, claims, key)
+    }
+
+    /// Public half of the keys. HMAC keys are not included.
+    pub fn public_keys(&self) -> JwtKeys {
+        JwtKeys {
+            keys: self.keys.iter().filter_map(SigningKey::public_key).collect(),
+        }
+    }
+
+    /// Public half of the keys as a JWKS document (`{"keys": [...]}`)
+    pub fn to_public_jwks(&self) -> Result<String, Error> {
+        serde_json::to_string(&self.public_keys()).map_err(|e| err_int("Failed to serialize keys").with_source(e))
+    }
+
+    /// Key store containing the public half of the keys
+    pub fn key_store(&self) -> Result<KeyStore, Error> {
+        let mut key_store = KeyStore::new();
+
+        for key in self.public_keys().keys.iter() {
+            key_store.add_key(key)?;
+        }
+
+        Ok(key_store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::encoder::SigningKey;
+    use crate::jwt::Header;
+    use crate::keyset::JwtKeys;
+    use crate::private_keyset::PrivateKeySet;
+
+    #[test]
+    fn test_generate_and_verify() {
+        let mut key_set = PrivateKeySet::new();
+
+        let es256 = key_set.generate_ec("ES256").unwrap();
+        let es384 = key_set.generate_ec("ES384").unwrap();
+        let ed25519 = key_set.generate_ed25519().unwrap();
+        let rs256 = key_set.add_key(SigningKey::from_pem("RS256", include_str!("../test/private.pem")).unwrap()).unwrap();
+
+        assert_eq!(4, key_set.keys_len());
+
+        let key_store = key_set.key_store().unwrap();
+
+        for kid in [es256, es384, ed25519, rs256].iter() {
+            let token = key_set.encode(kid, &Header::new(json!({ "typ": "JWT" })), &json!({ "sub": "sbu123" })).unwrap();
+
+            let jwt = key_store.verify(&token).unwrap();
+
+            assert_eq!(Some(kid.as_str()), jwt.header().kid());
+            assert_eq!(Some("sbu123"), jwt.payload().sub());
+        }
+    }
+
+    #[test]
+    fn test_thumbprint_kid() {
+        let mut key_set = PrivateKeySet::new();
+
+        let kid = key_set.add_key(SigningKey::from_pem("RS256", include_str!("../test/private.pem")).unwrap()).unwrap();
+
+        // RFC 7638 thumbprint of the key in test/test-jwks.json
+        assert_eq!("uIUwKZ-7cGupxT5K8cct1Cd4Kc3z8lrFAiyZh7ThsxY", kid);
+
+        let kid = key_set.add_key(SigningKey::from_secret("HS256", b"secret").unwrap().with_kid("shared")).unwrap();
+
+        assert_eq!("shared", kid);
+        assert!(key_set.add_key(SigningKey::from_secret("HS256", b"secret").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_export_public_jwks() {
+        let mut key_set = PrivateKeySet::new();
+
+        let kid = key_set.generate_ec("ES256").unwrap();
+        key_set.add_key(SigningKey::from_secret("HS256", b"secret").unwrap().with_kid("shared")).unwrap();
+
+        let jwks = JwtKeys::from_json(&key_set.to_public_jwks().unwrap()).unwrap();
+
+        assert_eq!(1, jwks.keys.len());
+
+        let key = jwks.key_by_id(&kid).unwrap();
+
+        assert_eq!("EC", key.kty);
+        assert_eq!("P-256", key.crv);
+        assert_eq!(Some("ES256".to_string()), key.alg);
+        assert!(key.n.is_empty());
+    }
+
+    #[cfg(feature = "rsa-keygen")]
+    #[test]
+    fn test_generate_rsa() {
+        let mut key_set = PrivateKeySet::new();
+
+        let kid = key_set.generate_rsa("PS256", 2048).unwrap();
+
+        let token = key_set.encode(&kid, &Header::new(json!({})), &json!({ "sub": "sbu123" })).unwrap();
+
+        assert!(key_set.key_store().unwrap().verify(&token).is_ok());
+    }
+}
@@ -0,0 +1,134 @@
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use arc_swap::ArcSwap;
+
+use crate::error::*;
+use crate::jwt::{Jwt, RawJwt};
+use crate::keyset::{fetch_source, KeyStore};
+
+/// `KeyStore` that can be shared between threads (ie: in an `Arc` or as web application state).
+///
+/// Verification reads an immutable snapshot of the store and never waits for an update or for the
+/// network. Only the verification cache, if enabled, is locked for the lookup and insertion of a
+/// token; snapshots share it until their keys change. Changes are made to a copy of the current
+/// store that then replaces it: key sets are
+/// downloaded first, then added to a copy of the store as it is at that moment, so a concurrent
+/// `update` is never lost.
+pub struct SharedKeyStore {
+    current: ArcSwap<KeyStore>,
+    /// Serializes the replacement of the current store
+    write: Mutex<()>,
+}
+
+impl Default for SharedKeyStore {
+    fn default() -> Self {
+        SharedKeyStore::new(KeyStore::new())
+    }
+}
+
+impl From<KeyStore> for SharedKeyStore {
+    fn from(key_store: KeyStore) -> Self {
+        SharedKeyStore::new(key_store)
+    }
+}
+
+impl SharedKeyStore {
+    pub fn new(key_store: KeyStore) -> SharedKeyStore {
+        SharedKeyStore {
+            current: ArcSwap::from_pointee(key_store),
+            write: Mutex::new(()),
+        }
+    }
+
+    /// Create a store for `jwks_url` and load its keys
+    pub async fn new_from(jwks_url: String) -> Result<SharedKeyStore, Error> {
+        Ok(SharedKeyStore::new(KeyStore::new_from(jwks_url).await?))
+    }
+
+    /// Current snapshot of the store. It is not affected by later changes.
+    pub fn key_store(&self) -> Arc<KeyStore> {
+        self.current.load_full()
+    }
+
+    /// Change the store (ie: add keys or sources, change settings) and return the result of `change`.
+    ///
+    /// `change` is applied to a copy of the current store that then replaces it. Verifications in
+    /// progress keep using the previous snapshot.
+    pub fn update<R, F: FnOnce(&mut KeyStore) -> R>(&self, change: F) -> R {
+        let _write = self.write.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut key_store = KeyStore::clone(&self.current.load());
+        let result = change(&mut key_store);
+        self.current.store(Arc::new(key_store));
+
+        result
+    }
+
+    /// Download the key sets of all sources and replace the store with one that has their keys.
+    ///
+    /// See `KeyStore::load_keys`.
+    pub async fn load_keys(&self) -> Result<(), Error> {
+        let (urls, timeout) = {
+            let key_store = self.current.load();
+            (key_store.source_urls(), key_store.load_timeout())
+        };
+
+        if urls.is_empty() {
+            return Err(err_con("No JWKS URL"));
+        }
+
+        self.load_sources(urls, timeout).await
+    }
+
+    /// Load the sources that are due for a refresh. See `KeyStore::refresh_key_sources`.
+    ///
+    /// The store is left untouched if no source is due. Concurrent calls may download the same
+    /// key set more than once.
+    pub async fn refresh_key_sources(&self) -> Result<(), Error> {
+        let (urls, timeout) = {
+            let key_store = self.current.load();
+            (key_store.due_sources(SystemTime::now()), key_store.load_timeout())
+        };
+
+        if urls.is_empty() {
+            #[cfg(feature = "metrics")]
+            self.current.load().record_metrics();
+
+            return Ok(());
+        }
+
+        self.load_sources(urls, timeout).await
+    }
+
+    async fn load_sources(&self, urls: Vec<String>, timeout: Option<Duration>) -> Result<(), Error> {
+        let client = self.current.load().http_client()?;
+        let mut results = Vec::with_capacity(urls.len());
+
+        for url in urls {
+            results.push(fetch_source(url, &client, timeout).await);
+        }
+
+        self.update(|key_store| key_store.add_key_sets(results))
+    }
+
+    /// See `KeyStore::verify`
+    pub fn verify(&self, token: &str) -> Result<Jwt, Error> {
+        self.current.load().verify(token)
+    }
+
+    /// See `KeyStore::verify_time`
+    pub fn verify_time(&self, token: &str, time: SystemTime) -> Result<Jwt, Error> {
+        self.current.load().verify_time(token, time)
+    }
+
+    /// See `KeyStore::verify_raw`
+    pub fn verify_raw(&self, token: &str) -> Result<RawJwt, Error> {
+        self.current.load().verify_raw(token)
+    }
+
+    /// See `KeyStore::verify_raw_time`
+    pub fn verify_raw_time(&self, token: &str, time: SystemTime) -> Result<RawJwt, Error> {
+        self.current.load().verify_raw_time(token, time)
+    }
+}
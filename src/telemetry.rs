@@ -0,0 +1,83 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use metrics::{counter, gauge, histogram};
+
+use crate::error::{Error, Type};
+use crate::keyset::KeySource;
+
+/// Verifications by `outcome`, `error_type` and `issuer`
+pub(crate) const VERIFICATIONS: &str = "jwks_client_verifications_total";
+/// Verification latency in seconds by `outcome`
+pub(crate) const VERIFY_DURATION: &str = "jwks_client_verify_duration_seconds";
+/// Key set downloads by `url`, `outcome` and `error_type`
+pub(crate) const FETCHES: &str = "jwks_client_key_set_fetches_total";
+/// Key set download latency in seconds by `url` and `outcome`
+pub(crate) const FETCH_DURATION: &str = "jwks_client_key_set_fetch_duration_seconds";
+/// Keys published by the source at `url`
+pub(crate) const KEYS: &str = "jwks_client_keys";
+/// Seconds until the keys of the source at `url` expire, negative once expired
+pub(crate) const EXPIRE_SECONDS: &str = "jwks_client_key_set_expire_seconds";
+/// UNIX time, in seconds, at which the keys of the source at `url` expire
+pub(crate) const EXPIRE_TIME: &str = "jwks_client_key_set_expire_timestamp_seconds";
+
+/// Issuer label of tokens whose issuer is not configured (or missing)
+pub(crate) const UNKNOWN_ISSUER: &str = "unknown";
+
+pub(crate) fn record_verification(error: Option<&Error>, issuer: Option<&str>, duration: Duration) {
+    let outcome = if error.is_some() { "rejected" } else { "verified" };
+
+    counter!(VERIFICATIONS, "outcome" => outcome, "error_type" => error_type(error), "issuer" => issuer.unwrap_or("").to_string()).increment(1);
+    histogram!(VERIFY_DURATION, "outcome" => outcome).record(duration.as_secs_f64());
+}
+
+pub(crate) fn record_fetch(url: &str, error: Option<&Error>, duration: Duration) {
+    let outcome = if error.is_some() { "failure" } else { "success" };
+
+    counter!(FETCHES, "url" => url.to_string(), "outcome" => outcome, "error_type" => error_type(error)).increment(1);
+    histogram!(FETCH_DURATION, "url" => url.to_string(), "outcome" => outcome).record(duration.as_secs_f64());
+}
+
+pub(crate) fn record_sources(sources: &[KeySource]) {
+    let now = SystemTime::now();
+
+    for source in sources {
+        gauge!(KEYS, "url" => source.url().to_string()).set(source.kids().len() as f64);
+
+        if let Some(expire_time) = source.expire_time() {
+            let remaining = match expire_time.duration_since(now) {
+                Ok(remaining) => remaining.as_secs_f64(),
+                Err(e) => -e.duration().as_secs_f64(),
+            };
+            gauge!(EXPIRE_SECONDS, "url" => source.url().to_string()).set(remaining);
+
+            let seconds = expire_time.duration_since(UNIX_EPOCH).map_or(0.0, |since_epoch| since_epoch.as_secs_f64());
+            gauge!(EXPIRE_TIME, "url" => source.url().to_string()).set(seconds);
+        }
+    }
+}
+
+/// The `metrics` facade cannot unregister gauges: the key count of a removed source drops to 0
+/// and its expiry gauges are no longer updated (exporters drop it after their idle timeout, if any)
+pub(crate) fn record_removed_source(url: &str) {
+    gauge!(KEYS, "url" => url.to_string()).set(0.0);
+}
+
+fn error_type(error: Option<&Error>) -> &'static str {
+    match error.map(|e| e.typ) {
+        None => "none",
+        Some(Type::Invalid) => "invalid",
+        Some(Type::Expired) => "expired",
+        Some(Type::Early) => "early",
+        Some(Type::Certificate) => "certificate",
+        Some(Type::Key) => "key",
+        Some(Type::Connection) => "connection",
+        Some(Type::Status) => "status",
+        Some(Type::Timeout) => "timeout",
+        Some(Type::Tls) => "tls",
+        Some(Type::Jwks) => "jwks",
+        Some(Type::Header) => "header",
+        Some(Type::Payload) => "payload",
+        Some(Type::Signature) => "signature",
+        Some(Type::Internal) => "internal",
+    }
+}
@@ -0,0 +1,321 @@
+use std::convert::TryFrom;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{decode_config, encode_config, STANDARD, URL_SAFE_NO_PAD};
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY, SHA256};
+use rustls_pki_types::{CertificateDer, SignatureVerificationAlgorithm, UnixTime};
+use webpki::{ExtendedKeyUsageValidator, KeyPurposeIdIter};
+
+use crate::error::*;
+use crate::keyset::PublicKey;
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+/// `[0] EXPLICIT`, used for the certificate version
+const TAG_CONTEXT_0: u8 = 0xa0;
+
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_P384: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+
+/// Signature algorithms accepted in certificate chains
+static CHAIN_ALGORITHMS: &[&dyn SignatureVerificationAlgorithm] = &[
+    webpki::ring::ECDSA_P256_SHA256,
+    webpki::ring::ECDSA_P256_SHA384,
+    webpki::ring::ECDSA_P384_SHA256,
+    webpki::ring::ECDSA_P384_SHA384,
+    webpki::ring::ED25519,
+    webpki::ring::RSA_PKCS1_2048_8192_SHA256,
+    webpki::ring::RSA_PKCS1_2048_8192_SHA384,
+    webpki::ring::RSA_PKCS1_2048_8192_SHA512,
+    webpki::ring::RSA_PKCS1_3072_8192_SHA384,
+    webpki::ring::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    webpki::ring::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    webpki::ring::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+];
+
+/// JWT signing has no registered extended key usage: any usage is accepted, but the extension
+/// must be well formed
+struct AnyKeyUsage;
+
+impl ExtendedKeyUsageValidator for AnyKeyUsage {
+    fn validate(&self, iter: KeyPurposeIdIter<'_, '_>) -> Result<(), webpki::Error> {
+        for id in iter {
+            id?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Minimal DER reader, sufficient to extract public keys
+struct Der<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    fn new(input: &'a [u8]) -> Der<'a> {
+        Der { input }
+    }
+
+    fn peek_tag(&self) -> Option<u8> {
+        self.input.first().copied()
+    }
+
+    /// Read the next element and return its tag, content and complete encoding
+    fn read_element(&mut self) -> Result<(u8, &'a [u8], &'a [u8]), Error> {
+        let invalid = || err_cer("Invalid DER encoding");
+
+        let tag = *self.input.first().ok_or_else(invalid)?;
+        let first = *self.input.get(1).ok_or_else(invalid)?;
+
+        let (length, header) = if first < 0x80 {
+            (first as usize, 2)
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 {
+                return Err(invalid());
+            }
+            let bytes = self.input.get(2..2 + count).ok_or_else(invalid)?;
+            (bytes.iter().fold(0usize, |length, byte| (length << 8) | *byte as usize), 2 + count)
+        };
+
+        let end = header.checked_add(length).ok_or_else(invalid)?;
+        let content = self.input.get(header..end).ok_or_else(invalid)?;
+
+        let element = &self.input[..end];
+        self.input = &self.input[end..];
+
+        Ok((tag, content, element))
+    }
+
+    /// Read the next element, which must have tag `tag`, and return its content
+    fn read(&mut self, tag: u8) -> Result<&'a [u8], Error> {
+        match self.read_element()? {
+            (actual, content, _) if actual == tag => Ok(content),
+            (actual, _, _) => Err(err_cer("Unexpected DER element").with_expected(format!("{:#04x}", tag), format!("{:#04x}", actual))),
+        }
+    }
+
+    /// Read an unsigned INTEGER without its leading zeros
+    fn read_unsigned(&mut self) -> Result<&'a [u8], Error> {
+        let content = self.read(TAG_INTEGER)?;
+
+        let start = content.iter().position(|b| *b != 0).unwrap_or(content.len());
+
+        Ok(&content[start..])
+    }
+}
+
+/// Public key of a `SubjectPublicKeyInfo` (RSA, EC P-256/P-384 or Ed25519)
+pub(crate) fn spki_public_key(der: &[u8]) -> Result<PublicKey, Error> {
+    let mut spki = Der::new(Der::new(der).read(TAG_SEQUENCE)?);
+
+    let mut algorithm = Der::new(spki.read(TAG_SEQUENCE)?);
+    let oid = algorithm.read(TAG_OID)?;
+
+    let key = spki.read(TAG_BIT_STRING)?;
+    // First byte is the number of unused bits, always 0 for keys
+    let key = match key.split_first() {
+        Some((0, key)) => key,
+        _ => return Err(err_cer("Invalid public key bit string")),
+    };
+
+    match oid {
+        OID_RSA_ENCRYPTION => rsa_public_key(key),
+        OID_EC_PUBLIC_KEY => {
+            let crv = match algorithm.read(TAG_OID)? {
+                OID_P256 => "P-256",
+                OID_P384 => "P-384",
+                _ => return Err(err_key("Unsupported curve").with_expected("P-256 or P-384", "other")),
+            };
+            let size = if crv == "P-256" { 32 } else { 48 };
+            // Only uncompressed points (`0x04 || x || y`) are supported
+            if key.len() != 1 + 2 * size || key[0] != 4 {
+                return Err(err_cer("Unsupported EC point encoding"));
+            }
+            PublicKey::from_ec_point(crv, key)
+        }
+        OID_ED25519 if key.len() == 32 => Ok(PublicKey::Ed25519 { x: key.to_vec() }),
+        OID_ED25519 => Err(err_cer("Invalid public key length")),
+        _ => Err(err_key("Unsupported public key algorithm")),
+    }
+}
+
+/// Public key of a PKCS#1 `RSAPublicKey`
+pub(crate) fn rsa_public_key(der: &[u8]) -> Result<PublicKey, Error> {
+    let mut key = Der::new(Der::new(der).read(TAG_SEQUENCE)?);
+
+    let n = key.read_unsigned()?.to_vec();
+    let e = key.read_unsigned()?.to_vec();
+
+    if n.is_empty() || e.is_empty() {
+        return Err(err_cer("Invalid RSA public key"));
+    }
+
+    Ok(PublicKey::Rsa { n, e })
+}
+
+/// Public key of a DER X.509 certificate
+pub(crate) fn certificate_public_key(der: &[u8]) -> Result<PublicKey, Error> {
+    let mut certificate = Der::new(der);
+    let mut tbs = Der::new(Der::new(certificate.read(TAG_SEQUENCE)?).read(TAG_SEQUENCE)?);
+
+    if tbs.peek_tag() == Some(TAG_CONTEXT_0) {
+        tbs.read(TAG_CONTEXT_0)?;
+    }
+
+    // serialNumber, signature, issuer, validity, subject
+    tbs.read(TAG_INTEGER)?;
+    for _ in 0..4 {
+        tbs.read(TAG_SEQUENCE)?;
+    }
+
+    match tbs.read_element()? {
+        (TAG_SEQUENCE, _, spki) => spki_public_key(spki),
+        _ => Err(err_cer("Invalid certificate public key")),
+    }
+}
+
+/// Decode an `x5c` list (standard base64 DER certificates, leaf first)
+pub(crate) fn decode_chain<S: AsRef<str>>(x5c: &[S]) -> Result<Vec<Vec<u8>>, Error> {
+    if x5c.is_empty() {
+        return Err(err_cer("Empty certificate chain"));
+    }
+
+    x5c.iter()
+        .map(|certificate| decode_config(certificate.as_ref(), STANDARD).map_err(|e| err_cer("Failed to decode certificate").with_source(e)))
+        .collect()
+}
+
+/// Check `x5t` (SHA-1) and `x5t#S256` (SHA-256) thumbprints against the leaf certificate
+pub(crate) fn check_thumbprints(leaf: &[u8], x5t: Option<&str>, x5t_s256: Option<&str>) -> Result<(), Error> {
+    if let Some(x5t) = x5t {
+        let actual = encode_config(&digest(&SHA1_FOR_LEGACY_USE_ONLY, leaf), URL_SAFE_NO_PAD);
+        if actual != x5t {
+            return Err(err_cer("x5t does not match certificate").with_expected(x5t, actual));
+        }
+    }
+
+    if let Some(x5t_s256) = x5t_s256 {
+        let actual = encode_config(&digest(&SHA256, leaf), URL_SAFE_NO_PAD);
+        if actual != x5t_s256 {
+            return Err(err_cer("x5t#S256 does not match certificate").with_expected(x5t_s256, actual));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that a DER root certificate can be used as trust anchor
+pub(crate) fn trust_anchor(der: &[u8]) -> Result<(), Error> {
+    let der = CertificateDer::from(der);
+
+    webpki::anchor_from_trusted_cert(&der).map(|_| ()).map_err(|e| err_cer("Invalid trust anchor").with_source(e))
+}
+
+/// Validate a certificate chain (leaf first) against DER trust anchors at `time`.
+///
+/// Only the path, validity periods and signatures are checked: unlike TLS certificates, signing
+/// certificates are not required to have a particular extended key usage or subject name.
+pub(crate) fn verify_chain(chain: &[Vec<u8>], trust_anchors: &[Vec<u8>], time: SystemTime) -> Result<(), Error> {
+    let (leaf, intermediates) = chain.split_first().ok_or_else(|| err_cer("Empty certificate chain"))?;
+
+    let anchors = trust_anchors.iter().map(|anchor| CertificateDer::from(anchor.as_slice())).collect::<Vec<_>>();
+    let anchors = anchors
+        .iter()
+        .map(|anchor| webpki::anchor_from_trusted_cert(anchor).map_err(|e| err_cer("Invalid trust anchor").with_source(e)))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let intermediates: Vec<CertificateDer> = intermediates.iter().map(|certificate| CertificateDer::from(certificate.as_slice())).collect();
+
+    let since_epoch = time.duration_since(UNIX_EPOCH).map_err(|_| err_int("Time is before UNIX epoch"))?;
+
+    let leaf = CertificateDer::from(leaf.as_slice());
+    let leaf = webpki::EndEntityCert::try_from(&leaf).map_err(|e| err_cer("Invalid certificate").with_source(e))?;
+
+    leaf.verify_for_usage(CHAIN_ALGORITHMS, &anchors, &intermediates, UnixTime::since_unix_epoch(since_epoch), AnyKeyUsage, None, None)
+        .map(|_| ())
+        .map_err(|e| err_cer("Certificate chain is not trusted").with_source(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::error::Type;
+    use crate::keyset::PublicKey;
+    use crate::pem::decode_pem;
+    use crate::x509::{certificate_public_key, check_thumbprints, rsa_public_key, spki_public_key, verify_chain};
+
+    fn der(pem: &str) -> Vec<u8> {
+        decode_pem(pem).unwrap().1
+    }
+
+    #[test]
+    fn test_certificate_public_key() {
+        let certificate = certificate_public_key(&der(include_str!("../test/cert.pem"))).unwrap();
+        let public = rsa_public_key(&der(include_str!("../test/public.pem"))).unwrap();
+
+        assert_eq!(public, certificate);
+
+        match certificate_public_key(&der(include_str!("../test/ca.pem"))).unwrap() {
+            PublicKey::Ec { crv, point } => {
+                assert_eq!("P-256", crv);
+                assert_eq!(65, point.len());
+            }
+            _ => panic!("Expected EC key"),
+        }
+
+        assert_eq!(Type::Certificate, certificate_public_key(&[0x30, 0x05, 0x01]).unwrap_err().typ);
+    }
+
+    #[test]
+    fn test_ec_point_on_curve() {
+        let mut spki = der(include_str!("../test/ec-public.pem"));
+
+        assert!(spki_public_key(&spki).is_ok());
+
+        let last = spki.len() - 1;
+        spki[last] ^= 1;
+
+        let error = spki_public_key(&spki).unwrap_err();
+        assert_eq!(Type::Certificate, error.typ);
+        assert_eq!("EC point is not on the curve", error.msg);
+    }
+
+    #[test]
+    fn test_thumbprints() {
+        let leaf = der(include_str!("../test/cert.pem"));
+
+        assert!(check_thumbprints(&leaf, Some("vYNWZ6d0xgzCPx_BWyuyIumkszY"), Some("0400MVChpJCLKS-EfOBuZsfo9dvS4h2hW2EoqZtwSq8")).is_ok());
+        assert!(check_thumbprints(&leaf, None, None).is_ok());
+        assert_eq!(Type::Certificate, check_thumbprints(&leaf, Some("wrong"), None).unwrap_err().typ);
+        assert_eq!(Type::Certificate, check_thumbprints(&leaf, None, Some("wrong")).unwrap_err().typ);
+    }
+
+    #[test]
+    fn test_verify_chain() {
+        let chain = vec![der(include_str!("../test/cert.pem"))];
+        let ca = vec![der(include_str!("../test/ca.pem"))];
+        let other_ca = vec![der(include_str!("../test/other-ca.pem"))];
+
+        let now = SystemTime::now();
+
+        assert!(verify_chain(&chain, &ca, now).is_ok());
+        assert_eq!(Type::Certificate, verify_chain(&chain, &other_ca, now).unwrap_err().typ);
+        // Before the certificate is valid
+        assert!(verify_chain(&chain, &ca, UNIX_EPOCH + Duration::new(1_000_000_000, 0)).is_err());
+
+        // Signing certificates are not required to be TLS server certificates
+        let code_signing = vec![der(include_str!("../test/code-signing-cert.pem"))];
+        let code_signing_ca = vec![der(include_str!("../test/code-signing-ca.pem"))];
+
+        assert!(verify_chain(&code_signing, &code_signing_ca, now).is_ok());
+        assert_eq!(Type::Certificate, verify_chain(&code_signing, &ca, now).unwrap_err().typ);
+    }
+}